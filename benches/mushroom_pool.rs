@@ -0,0 +1,53 @@
+//Mushroom churn in a headless app, parking dead mushrooms in the pool against despawning them
+//Run with `cargo bench --bench mushroom_pool`
+
+//The whole game comes along, tests and all, only its churn module is used here
+#[path = "../src/main.rs"]
+#[allow(dead_code, unused_imports)]
+mod game;
+
+use game::churn;
+use std::time::{Duration, Instant};
+
+const FRAMES: u32 = 2000;
+
+//Average frame time once the population has filled up
+fn run(pool_capacity: usize) -> (Duration, usize) {
+    let mut app = churn::app(pool_capacity);
+
+    for _ in 0..churn::POPULATION / churn::SPAWNS_PER_FRAME * 2 {
+        app.update();
+    }
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        app.update();
+    }
+    (
+        start.elapsed() / FRAMES,
+        app.world.entities().len() as usize,
+    )
+}
+
+fn main() {
+    let (despawn, despawn_entities) = run(0);
+    let (pooled, pooled_entities) = run(churn::POOL_CAPACITY);
+
+    println!(
+        "{} mushrooms, {} spawned and killed per frame, {FRAMES} frames",
+        churn::POPULATION,
+        churn::SPAWNS_PER_FRAME
+    );
+    println!(
+        "{:<10} {:>10.3?} per frame   {despawn_entities:>6} entities",
+        "despawn", despawn
+    );
+    println!(
+        "{:<10} {:>10.3?} per frame   {pooled_entities:>6} entities",
+        "pooled", pooled
+    );
+    println!(
+        "pooling is {:.1}x faster",
+        despawn.as_secs_f64() / pooled.as_secs_f64()
+    );
+}
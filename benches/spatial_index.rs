@@ -0,0 +1,133 @@
+//Range queries over 10k mushrooms, the grid against scanning every mushroom
+//Run with `cargo bench --bench spatial_index`
+
+//Its unit tests come along too, with no test harness here to run them
+#[path = "../src/spatial.rs"]
+#[allow(dead_code)]
+mod spatial;
+
+use bevy::math::Vec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use spatial::SpatialGrid;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const MUSHROOM_COUNT: usize = 10_000;
+const HERO_COUNT: usize = 20;
+//Same layout as the game, three lanes 48px apart on a 1280px wide screen
+const FIELD_WIDTH: f32 = 1280.0;
+const LANES: [f32; 3] = [0.0, -48.0, -96.0];
+const LANE_HALF_WIDTH: f32 = 20.0;
+const CELL_SIZE: f32 = 64.0;
+const HERO_RANGE: f32 = 250.0;
+const MUSHROOM_RANGE: f32 = 50.0;
+const SEPARATION_REACH: f32 = 25.3;
+
+fn battlefield(count: usize, rng: &mut StdRng) -> Vec<Vec2> {
+    (0..count)
+        .map(|_| {
+            let lane = LANES[rng.gen_range(0..LANES.len())];
+            Vec2::new(
+                rng.gen_range(-FIELD_WIDTH / 2.0..FIELD_WIDTH / 2.0),
+                lane + rng.gen_range(-LANE_HALF_WIDTH..LANE_HALF_WIDTH),
+            )
+        })
+        .collect()
+}
+
+//Average time per run, after one warm up run
+fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    start.elapsed() / runs
+}
+
+fn report(name: &str, brute_force: Duration, grid: Duration) {
+    println!(
+        "{name:<28} brute force {:>10.3?}   grid {:>10.3?}   {:>6.1}x",
+        brute_force,
+        grid,
+        brute_force.as_secs_f64() / grid.as_secs_f64()
+    );
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(55);
+    let mushrooms = battlefield(MUSHROOM_COUNT, &mut rng);
+    let heroes = battlefield(HERO_COUNT, &mut rng);
+
+    let mut mushroom_grid = SpatialGrid::new(CELL_SIZE);
+    let mut hero_grid = SpatialGrid::new(CELL_SIZE);
+    let rebuild = time(100, || {
+        mushroom_grid.clear();
+        for (index, &position) in mushrooms.iter().enumerate() {
+            mushroom_grid.insert(position, index);
+        }
+    });
+    for (index, &position) in heroes.iter().enumerate() {
+        hero_grid.insert(position, index);
+    }
+    println!("{MUSHROOM_COUNT} mushrooms, {HERO_COUNT} heroes");
+    println!("{:<28} {rebuild:>10.3?}", "rebuild mushroom grid");
+
+    //hero_attack_system, every hero looks for mushrooms in range
+    let brute_force = time(100, || {
+        for hero in heroes.iter() {
+            let in_range = mushrooms
+                .iter()
+                .filter(|mushroom| mushroom.distance(*hero) <= HERO_RANGE)
+                .count();
+            black_box(in_range);
+        }
+    });
+    let grid = time(100, || {
+        for hero in heroes.iter() {
+            black_box(mushroom_grid.query_radius(*hero, HERO_RANGE).count());
+        }
+    });
+    report("heroes find targets", brute_force, grid);
+
+    //mushroom_attack_system, every mushroom looks for the nearest hero in range
+    let brute_force = time(20, || {
+        for mushroom in mushrooms.iter() {
+            let nearest = heroes
+                .iter()
+                .map(|hero| hero.distance(*mushroom))
+                .filter(|&distance| distance <= MUSHROOM_RANGE)
+                .min_by(|a, b| a.total_cmp(b));
+            black_box(nearest);
+        }
+    });
+    let grid = time(20, || {
+        for mushroom in mushrooms.iter() {
+            black_box(hero_grid.nearest(*mushroom, MUSHROOM_RANGE));
+        }
+    });
+    report("mushrooms find a hero", brute_force, grid);
+
+    //mushroom_movement_system, every mushroom looks for crowd neighbours
+    let brute_force = time(1, || {
+        for mushroom in mushrooms.iter() {
+            let neighbours = mushrooms
+                .iter()
+                .filter(|other| other.distance(*mushroom) <= SEPARATION_REACH)
+                .count();
+            black_box(neighbours);
+        }
+    });
+    let mut crowd_grid = SpatialGrid::new(SEPARATION_REACH);
+    for (index, &position) in mushrooms.iter().enumerate() {
+        crowd_grid.insert(position, index);
+    }
+    let grid = time(20, || {
+        for mushroom in mushrooms.iter() {
+            black_box(crowd_grid.query_radius(*mushroom, SEPARATION_REACH).count());
+        }
+    });
+    report("crowd separation", brute_force, grid);
+}
@@ -1,21 +1,180 @@
 use bevy::{prelude::*, utils::HashMap, window::PrimaryWindow};
-use rand::Rng;
-use std::ops::Index;
+use pool::EntityPool;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spatial::SpatialGrid;
+use std::{collections::VecDeque, ops::Index};
+
+mod pool;
+mod spatial;
 
 const GLOBAL_SCALE: f32 = 1.0;
 const TILE_SIZE: f32 = 64.0;
 const INITIAL_SPORE_COUNT: i32 = 15;
 // const INITIAL_SPORE_COUNT: i32 = 1000;
 
+//BATTLEFIELD
+//Lanes run left to right, the first one lines up with the base
+const LANE_COUNT: usize = 3;
+const LANE_SPACING: f32 = 48.0;
+//Units spawn this far above or below the middle of their lane
+const LANE_JITTER: f32 = 8.0;
+const LANE_MARKER_HEIGHT: f32 = 4.0;
+const LANE_MARKER_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.2);
+//Units lower on screen are drawn in front, z stays within UNIT_Z +- 1 for any sane window
+const UNIT_Z: f32 = 5.0;
+const Y_SORT_DEPTH_PER_PIXEL: f32 = 0.001;
+
+//CROWD
+//Mushrooms closer than this push each other apart
+const SEPARATION_RADIUS: f32 = 20.0;
+//Top speed of the push, reached when two mushrooms sit on the same spot
+const SEPARATION_SPEED: f32 = 60.0;
+//A stopped mushroom this close ahead and roughly in line blocks the one behind it
+const QUEUE_DISTANCE: f32 = 24.0;
+const QUEUE_WIDTH: f32 = 8.0;
+//Crowds are kept this far inside the edge of their lane
+const LANE_EDGE_MARGIN: f32 = 4.0;
+//Mushrooms with no hero in their lane cross over to the nearest hero's lane this fast
+const LANE_CHANGE_SPEED: f32 = 40.0;
+const FORMATION_SPACING: f32 = 14.0;
+const FORMATION_CYCLE_KEY: KeyCode = KeyCode::F;
+
+//SPATIAL INDEX
+//About the reach of a melee unit, ranged queries just cover a few more cells
+const SPATIAL_INDEX_CELL_SIZE: f32 = 64.0;
+
 //HERO
-const HERO_BASE_HP: f32 = 1000000.0;
-const HERO_BASE_ATK: f32 = 10.0;
-const HERO_BASE_MOVE_SPEED: f32 = 10.0;
-const HERO_BASE_ATK_SPEED: f32 = 1.0;
-const HERO_BASE_ATK_RANGE: f32 = 50.0;
 const HERO_BASE_LEVEL: i32 = 1;
-const HERO_BASE_EXP_REQUIRED: f32 = 200.0;
-const HERO_EXP_PER_SECOND: f32 = 1.0;
+//Heroes stand still for this long after walking in
+const HERO_SPAWN_TIME: f32 = 0.5;
+//Passive exp trickle, most exp should come from killing mushrooms
+const HERO_EXP_PER_SECOND: f32 = 0.2;
+//Scales the xp_drop of every mushroom a hero kills, 0 turns kill exp off
+const HERO_KILL_EXP_MULTIPLIER: f32 = 1.0;
+const HERO_CLASSES: &str = include_str!("../assets/heroes.ron");
+
+//ENCOUNTER
+const WAVE_SCRIPT: &str = include_str!("../assets/waves.ron");
+
+//ANIMATION
+const ANIMATIONS: &str = include_str!("../assets/animations.ron");
+
+//ENDLESS
+//Time between endless waves, they overlap if the last one is still alive
+const ENDLESS_WAVE_DELAY: f32 = 15.0;
+const ENDLESS_BASE_HERO_COUNT: usize = 1;
+const ENDLESS_WAVES_PER_EXTRA_HERO: usize = 2;
+const ENDLESS_HERO_SPAWN_INTERVAL: f32 = 2.0;
+//Endless heroes spawn a level higher for every this many seconds survived / heroes killed
+const ENDLESS_SECONDS_PER_LEVEL: f32 = 60.0;
+const ENDLESS_KILLS_PER_LEVEL: f32 = 5.0;
+
+//CAMPAIGN
+const CAMPAIGN: &str = include_str!("../assets/campaign.ron");
+const CAMPAIGN_PROGRESS_FILE: &str = "campaign_progress.ron";
+const LOCKED_LEVEL_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+//RUN SETUP
+const SELECTED_BUTTON: Color = Color::rgb(0.5, 0.9, 0.5);
+const UPGRADE_CAP_LEVEL: u32 = 5;
+const SPORE_DECAY_RATE: f32 = 0.02;
+
+//DAILY CHALLENGE
+const DAILY_LEADERBOARD_FILE: &str = "daily_leaderboard.ron";
+const DAILY_MUTATOR_CHANCE: f64 = 0.35;
+const DAILY_MIN_STARTING_SPORES: i32 = 10;
+const DAILY_MAX_STARTING_SPORES: i32 = 60;
+
+//HIGH SCORES
+const HIGH_SCORE_FILE: &str = "highscores.ron";
+const HIGH_SCORES_KEPT: usize = 10;
+const HIGH_SCORES_SHOWN: usize = 5;
+
+//HERO ABILITIES
+const HERO_TELEGRAPH_TIME: f32 = 0.75;
+const CHARGE_COOLDOWN: f32 = 10.0;
+const CHARGE_DISTANCE: f32 = 200.0;
+const CHARGE_SPEED: f32 = 800.0;
+const CHARGE_TRIGGER_RANGE: f32 = 300.0;
+const CHARGE_ATK_MULTIPLIER: f32 = 1.5;
+const CHARGE_TELEGRAPH_HEIGHT: f32 = 0.5;
+const WHIRLWIND_COOLDOWN: f32 = 8.0;
+const WHIRLWIND_RADIUS: f32 = 100.0;
+const WHIRLWIND_MIN_TARGETS: usize = 3;
+const WHIRLWIND_ATK_MULTIPLIER: f32 = 2.0;
+const HEAL_COOLDOWN: f32 = 30.0;
+const HEAL_HP_THRESHOLD: f32 = 0.3;
+const HEAL_AMOUNT: f32 = 0.25;
+const HEAL_RADIUS: f32 = 150.0;
+const ENRAGE_TIME_LIMIT: f32 = 300.0;
+const ENRAGE_TELEGRAPH_RADIUS: f32 = 150.0;
+
+const CHARGE_EFFECT: StatusEffect = StatusEffect {
+    kind: StatusEffectKind::Stun,
+    magnitude: 0.0,
+    duration: 1.0,
+};
+const ENRAGE_EFFECTS: [StatusEffect; 2] = [
+    StatusEffect {
+        kind: StatusEffectKind::Rage,
+        magnitude: 1.0,
+        duration: f32::INFINITY,
+    },
+    StatusEffect {
+        kind: StatusEffectKind::Haste,
+        magnitude: 0.5,
+        duration: f32::INFINITY,
+    },
+];
+
+const CHARGE_TELEGRAPH_COLOR: Color = Color::rgba(1.0, 0.6, 0.2, 0.4);
+const WHIRLWIND_TELEGRAPH_COLOR: Color = Color::rgba(1.0, 0.2, 0.2, 0.4);
+const HEAL_TELEGRAPH_COLOR: Color = Color::rgba(0.2, 1.0, 0.4, 0.4);
+const ENRAGE_TELEGRAPH_COLOR: Color = Color::rgba(0.8, 0.0, 0.0, 0.4);
+
+//HERO PHASES
+const HERO_PHASES: [HeroPhase; 3] = [
+    HeroPhase {
+        hp_threshold: 1.0,
+        atk_multiplier: 1.0,
+        atk_speed_multiplier: 1.0,
+        move_speed_multiplier: 1.0,
+        scale: 1.0,
+        tint: Color::WHITE,
+    },
+    HeroPhase {
+        hp_threshold: 0.66,
+        atk_multiplier: 1.5,
+        atk_speed_multiplier: 1.25,
+        move_speed_multiplier: 1.0,
+        scale: 1.15,
+        tint: Color::rgb(1.0, 0.8, 0.8),
+    },
+    HeroPhase {
+        hp_threshold: 0.33,
+        atk_multiplier: 2.0,
+        atk_speed_multiplier: 1.5,
+        move_speed_multiplier: 1.5,
+        scale: 1.3,
+        tint: Color::rgb(1.0, 0.5, 0.5),
+    },
+];
+
+//HERO TARGETING
+const HERO_CLEAVE_MAX_TARGETS: usize = 3;
+const HERO_SPLASH_RADIUS: f32 = 60.0;
+const HERO_SPLASH_DAMAGE_MULTIPLIER: f32 = 0.5;
+const HERO_TARGETING_CYCLE_KEY: KeyCode = KeyCode::T;
+//Left click spawns mushrooms, so heroes are picked with the other button
+const HERO_SELECT_BUTTON: MouseButton = MouseButton::Right;
+const HERO_SELECT_RADIUS: f32 = 48.0;
+const SELECTION_RING_SIZE: f32 = 24.0;
+const SELECTION_RING_COLOR: Color = Color::rgba(1.0, 0.9, 0.2, 0.6);
+const TARGET_MARKER_SIZE: f32 = 10.0;
+const TARGET_MARKER_OFFSET_Y: f32 = 40.0;
+const TARGET_MARKER_COLOR: Color = Color::RED;
+const SPLASH_INDICATOR_COLOR: Color = Color::rgba(1.0, 0.3, 0.3, 0.3);
 
 //MUSHROOM
 const MUSHROOM_BASE_HP: f32 = 10.0;
@@ -24,9 +183,27 @@ const MUSHROOM_BASE_MOVE_SPEED: f32 = 100.0;
 const MUSHROOM_BASE_ATK_SPEED: f32 = 1.0;
 const MUSHROOM_BASE_ATK_RANGE: f32 = 50.0;
 const MUSHROOM_BASE_SPORE_COUNT: i32 = 3;
-const MUSHROOM_BASE_EXP_DROP: f32 = 1.0;
+const MUSHROOM_BASE_EXP_DROP: f32 = 5.0;
+const MUSHROOM_BASE_ARMOR: f32 = 0.0;
+const MUSHROOM_BASE_TOXIC_RESISTANCE: f32 = 0.5;
+const MUSHROOM_BASE_CRIT_CHANCE: f32 = 0.05;
+const MUSHROOM_BASE_CRIT_MULTIPLIER: f32 = 1.5;
 
 const MUSHROOM_SPAWN_POSITION_OFFSET_AMOUNT: f32 = 5.0;
+const MUSHROOM_SPAWN_TIME: f32 = 0.25;
+//Dead mushrooms parked for reuse, any past this are despawned
+const MUSHROOM_POOL_CAPACITY: usize = 2000;
+
+//SPAWN QUEUE
+//Seconds to grow one mushroom, a batch takes its unit's build time times its size
+const MUSHROOM_BUILD_TIME: f32 = 0.2;
+//Clicks are ignored while this many batches are waiting
+const SPAWN_QUEUE_LENGTH: usize = 5;
+//Live and queued mushrooms together can't go past this, the lord doesn't count
+const BASE_POPULATION_CAP: i32 = 50;
+const SPAWN_QUEUE_SLOT_SIZE: f32 = 40.0;
+const SPAWN_QUEUE_SLOT_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.5);
+const SPAWN_QUEUE_FILL_COLOR: Color = Color::rgba(0.4, 0.8, 0.3, 0.8);
 
 //MUSHROOM LORD
 const MUSHROOM_LORD_BASE_HP: f32 = 10.0;
@@ -37,6 +214,11 @@ const MUSHROOM_LORD_BASE_ATK_RANGE: f32 = 100.0;
 const MUSHROOM_LORD_BASE_SPORE_COUNT: i32 = 0;
 const MUSHROOM_LORD_BASE_EXP_DROP: f32 = 0.0;
 const MUSHROOM_LORD_SCALE: f32 = 3.0;
+const MUSHROOM_LORD_BASE_ARMOR: f32 = 50.0;
+const MUSHROOM_LORD_BASE_TOXIC_RESISTANCE: f32 = 0.75;
+const MUSHROOM_LORD_BASE_CRIT_CHANCE: f32 = 0.2;
+const MUSHROOM_LORD_BASE_CRIT_MULTIPLIER: f32 = 2.0;
+const MUSHROOM_LORD_BUILD_TIME: f32 = 3.0;
 
 const MUSHROOM_LORD_SPORE_MULTIPLIER_HP: f32 = 0.1;
 const MUSHROOM_LORD_SPORE_MULTIPLIER_ATK: f32 = 0.1;
@@ -46,6 +228,125 @@ const MUSHROOM_LORD_SPORE_MULTIPLIER_ATK_RANGE: f32 = 0.0;
 // const MUSHROOM_LORD_SPORE_MULTIPLIER_SPORE_COUNT: i32 = 0;
 // const MUSHROOM_LORD_SPORE_MULTIPLIER_EXP_DROP: f32 = 0.0;
 
+//DEATH
+//Corpses fade out for this long once the die clip is over
+const CORPSE_FADE_TIME: f32 = 0.6;
+const SPORE_PARTICLES_MAX: i32 = 8;
+const SPORE_PARTICLE_BURST_SPEED: f32 = 200.0;
+//Particles scatter for this long before flying to the spore counter
+const SPORE_PARTICLE_BURST_TIME: f32 = 0.35;
+const SPORE_PARTICLE_DRAG: f32 = 4.0;
+const SPORE_PARTICLE_SPEED: f32 = 900.0;
+const SPORE_PARTICLE_SIZE: f32 = 6.0;
+const SPORE_PARTICLE_COLOR: Color = Color::rgb(0.8, 1.0, 0.3);
+
+//COMBAT FEEDBACK
+const SETTINGS_FILE: &str = "settings.ron";
+const FLOATING_TEXT_LIFETIME: f32 = 0.8;
+const FLOATING_TEXT_SPEED: f32 = 60.0;
+const FLOATING_TEXT_FONT_SIZE: f32 = 18.0;
+const FLOATING_TEXT_CRIT_FONT_SIZE: f32 = 26.0;
+const FLOATING_TEXT_OFFSET_Y: f32 = 40.0;
+const PHYSICAL_DAMAGE_COLOR: Color = Color::WHITE;
+const TOXIC_DAMAGE_COLOR: Color = Color::rgb(0.5, 1.0, 0.3);
+const TRUE_DAMAGE_COLOR: Color = Color::rgb(0.8, 0.5, 1.0);
+const CRIT_DAMAGE_COLOR: Color = Color::rgb(1.0, 0.85, 0.2);
+const HIT_FLASH_TIME: f32 = 0.1;
+const HIT_FLASH_COLOR: Color = Color::rgb(1.0, 0.35, 0.35);
+//Hits at least this big shake the screen
+const SCREEN_SHAKE_MIN_DAMAGE: f32 = 30.0;
+const SCREEN_SHAKE_HIT_TRAUMA: f32 = 0.3;
+const SCREEN_SHAKE_SUMMON_TRAUMA: f32 = 0.6;
+const SCREEN_SHAKE_DECAY: f32 = 1.5;
+const SCREEN_SHAKE_MAX_OFFSET: f32 = 12.0;
+
+//MUSHROOM BASE
+const BASE_STRUCTURE_HP: f32 = 500.0;
+const BASE_STRUCTURE_ARMOR: f32 = 50.0;
+const BASE_STRUCTURE_TOXIC_RESISTANCE: f32 = 0.0;
+const BASE_STRUCTURE_REGEN: f32 = 0.0;
+const BASE_HEALTH_BAR_WIDTH: f32 = 64.0;
+const BASE_HEALTH_BAR_HEIGHT: f32 = 6.0;
+const BASE_HEALTH_BAR_OFFSET_Y: f32 = 40.0;
+//Unit bars are sized for a 64px sprite and grow with the unit's scale
+const UNIT_HEALTH_BAR_WIDTH: f32 = 40.0;
+const UNIT_HEALTH_BAR_HEIGHT: f32 = 4.0;
+const UNIT_HEALTH_BAR_OFFSET_Y: f32 = 30.0;
+const HEALTH_BAR_BACKGROUND_COLOR: Color = Color::rgb(0.2, 0.0, 0.0);
+const HEALTH_BAR_FILL_COLOR: Color = Color::rgb(0.2, 0.9, 0.2);
+
+//DAMAGE
+//Armor needed to halve physical damage
+const ARMOR_HALF_DAMAGE_POINT: f32 = 100.0;
+const MAX_RESISTANCE: f32 = 0.9;
+
+//MUSHROOM LORD ABILITIES
+const MUSHROOM_LORD_AUTO_CAST_KEY: KeyCode = KeyCode::A;
+const SPORE_NOVA_KEY: KeyCode = KeyCode::Q;
+const SPORE_NOVA_COOLDOWN: f32 = 8.0;
+const SPORE_NOVA_RADIUS: f32 = 200.0;
+const SPORE_NOVA_ATK_MULTIPLIER: f32 = 2.0;
+const RALLY_KEY: KeyCode = KeyCode::W;
+const RALLY_COOLDOWN: f32 = 15.0;
+const RALLY_RADIUS: f32 = 300.0;
+const ROOT_KEY: KeyCode = KeyCode::E;
+const ROOT_COOLDOWN: f32 = 12.0;
+const ROOT_RANGE: f32 = 400.0;
+const SPAWN_MINIONS_KEY: KeyCode = KeyCode::R;
+const SPAWN_MINIONS_COOLDOWN: f32 = 20.0;
+const SPAWN_MINIONS_COUNT: i32 = 5;
+//The lord grows its minions much faster than the base does
+const SPAWN_MINIONS_BUILD_TIME: f32 = 0.05;
+
+const SPORE_NOVA_EFFECT: StatusEffect = StatusEffect {
+    kind: StatusEffectKind::Poison,
+    magnitude: 2.0,
+    duration: 5.0,
+};
+const RALLY_EFFECTS: [StatusEffect; 2] = [
+    StatusEffect {
+        kind: StatusEffectKind::Rage,
+        magnitude: 0.5,
+        duration: 8.0,
+    },
+    StatusEffect {
+        kind: StatusEffectKind::Haste,
+        magnitude: 0.3,
+        duration: 8.0,
+    },
+];
+const ROOT_EFFECT: StatusEffect = StatusEffect {
+    kind: StatusEffectKind::Stun,
+    magnitude: 0.0,
+    duration: 2.0,
+};
+
+//STATUS EFFECTS
+const STATUS_EFFECT_TICK_INTERVAL: f32 = 0.5;
+const POISON_MAX_STACKS: i32 = 5;
+
+const MUSHROOM_ON_HIT_EFFECT: StatusEffect = StatusEffect {
+    kind: StatusEffectKind::Poison,
+    magnitude: 0.5,
+    duration: 3.0,
+};
+const MUSHROOM_LORD_ON_HIT_EFFECT: StatusEffect = StatusEffect {
+    kind: StatusEffectKind::Stun,
+    magnitude: 0.0,
+    duration: 0.5,
+};
+const MUSHROOM_LORD_SUMMON_EFFECT: StatusEffect = StatusEffect {
+    kind: StatusEffectKind::Haste,
+    magnitude: 0.5,
+    duration: 10.0,
+};
+
+const POISON_TINT: Color = Color::rgb(0.4, 1.0, 0.4);
+const SLOW_TINT: Color = Color::rgb(0.4, 0.6, 1.0);
+const STUN_TINT: Color = Color::rgb(1.0, 1.0, 0.3);
+const RAGE_TINT: Color = Color::rgb(1.0, 0.4, 0.4);
+const HASTE_TINT: Color = Color::rgb(1.0, 0.7, 0.3);
+
 //UI
 const NORMAL_BUTTON: Color = Color::rgb(1.0, 1.0, 1.0);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
@@ -58,6 +359,19 @@ const UPGRADE_MUSHROOM_HP_BASE_COST: i32 = 500;
 const UPGRADE_MUSHROOM_ATK_BASE_COST: i32 = 100;
 const UPGRADE_MUSHROOM_ATK_SPEED_BASE_COST: i32 = 10;
 const UPGRADE_MUSHROOM_MOVE_SPEED_BASE_COST: i32 = 100;
+const UPGRADE_MUSHROOM_CRIT_CHANCE_BASE_COST: i32 = 200;
+const UPGRADE_MUSHROOM_CRIT_MULTIPLIER_BASE_COST: i32 = 300;
+
+const UPGRADE_BASE_WALLS_BASE_COST: i32 = 150;
+const UPGRADE_BASE_REGEN_BASE_COST: i32 = 150;
+const UPGRADE_POPULATION_CAP_BASE_COST: i32 = 150;
+
+const UPGRADE_CRIT_CHANCE_AMOUNT: f32 = 0.05;
+const UPGRADE_CRIT_MULTIPLIER_AMOUNT: f32 = 0.25;
+const UPGRADE_BASE_WALLS_HP_AMOUNT: f32 = 250.0;
+const UPGRADE_BASE_WALLS_ARMOR_AMOUNT: f32 = 10.0;
+const UPGRADE_BASE_REGEN_AMOUNT: f32 = 2.0;
+const UPGRADE_POPULATION_CAP_AMOUNT: i32 = 25;
 
 const UPGRADE_COST_BASE_MULTIPLIER: i32 = 2;
 const UPGRADE_COST_SPORE_COUNT_MULTIPLIER: i32 = 3;
@@ -67,18 +381,43 @@ const UPGRADE_COST_MUSHROOMS_PER_CLICK_MULTIPLIER: i32 = 3;
 const SUMMON_BUTTON_INACTIVE_COLOR: BackgroundColor = BackgroundColor(Color::GRAY);
 const SUMMON_BUTTON_ACTIVE_COLOR: BackgroundColor = BackgroundColor(Color::WHITE);
 const SUMMON_MINIMUM_SPORE_COUNT: i32 = 1000;
+const SUMMON_COOLDOWN: f32 = 30.0;
+const SUMMON_SACRIFICE_STEP: i32 = 100;
+const SUMMON_PREVIEW_WIDTH: f32 = 240.0;
+
+const MUSHROOM_LORD_TIERS: [MushroomLordTier; 3] = [
+    MushroomLordTier {
+        name: "Mushroom Lord",
+        required_spores_sacrificed: 0,
+        stat_multiplier: 1.0,
+        scale: MUSHROOM_LORD_SCALE,
+        tint: Color::WHITE,
+    },
+    MushroomLordTier {
+        name: "Mushroom King",
+        required_spores_sacrificed: 5000,
+        stat_multiplier: 1.5,
+        scale: 3.5,
+        tint: Color::rgb(0.8, 0.6, 1.0),
+    },
+    MushroomLordTier {
+        name: "Mushroom Emperor",
+        required_spores_sacrificed: 20000,
+        stat_multiplier: 2.5,
+        scale: 4.0,
+        tint: Color::rgb(1.0, 0.85, 0.3),
+    },
+];
 
 //Etc
 const BASE_MUSHROOMS_PER_CLICK: i32 = 1;
 
 #[derive(Eq, Hash, PartialEq)]
 enum ImageType {
-    Mushroom,
-    Hero,
     MushroomBase,
     Ground,
     Background,
-    HeroAttack,
+    SummonCircle,
 }
 
 #[derive(Resource)]
@@ -91,12 +430,14 @@ struct SpriteImage {
 }
 
 impl SpriteImage {
+    #[allow(clippy::needless_return)]
     fn handle(&self) -> Handle<Image> {
         return self.image_handle.clone_weak();
     }
 }
 
 impl ImageManager {
+    #[allow(clippy::needless_return)]
     fn get(&self, key: ImageType) -> &SpriteImage {
         return &self.images[&key];
     }
@@ -115,473 +456,2859 @@ struct GameOverText;
 #[derive(Component)]
 struct GameOverUI;
 
-#[derive(Component)]
-struct GameManager {
-    game_over: bool,
-    victory: bool,
+#[derive(States, Default, Clone, Copy, Eq, PartialEq, Hash, Debug)]
+enum AppState {
+    #[default]
+    Menu,
+    LevelSelect,
+    RunSetup,
+    InGame,
 }
 
-impl Default for GameManager {
-    fn default() -> Self {
-        GameManager {
-            game_over: false,
-            victory: false,
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+enum GameMode {
+    #[default]
+    Waves,
+    Endless,
+    Campaign,
+    Daily,
+}
+
+impl GameMode {
+    fn name(&self) -> &'static str {
+        match self {
+            GameMode::Waves => "Waves",
+            GameMode::Endless => "Endless",
+            GameMode::Campaign => "Campaign",
+            GameMode::Daily => "Daily",
         }
     }
+
+    //Endless waves come on a timer, scripted ones wait for the board to clear
+    fn scheduled_waves(&self) -> bool {
+        matches!(self, GameMode::Endless | GameMode::Daily)
+    }
 }
 
-#[derive(Component)]
-struct HeroAttackAudio;
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize,
+)]
+enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
 
-#[derive(Component)]
-struct GameCamera;
+impl Difficulty {
+    fn modifiers(&self) -> RunModifiers {
+        match self {
+            Difficulty::Easy => RunModifiers {
+                hero_hp: 0.75,
+                hero_atk: 0.75,
+                spore_income: 1.5,
+                upgrade_cost: 0.75,
+                ..default()
+            },
+            Difficulty::Normal => RunModifiers::default(),
+            Difficulty::Hard => RunModifiers {
+                hero_hp: 1.5,
+                hero_atk: 1.5,
+                spore_income: 0.75,
+                upgrade_cost: 1.25,
+                ..default()
+            },
+        }
+    }
+}
 
-#[derive(Component)]
-struct UpgradeButton {
-    upgrade_type: UpgradeType,
-    cost: i32,
-    cost_multiplier: i32,
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+enum Mutator {
+    GlassMushrooms,
+    DoubleSpeedHero,
+    UpgradeCap,
+    SporeDecay,
 }
-#[derive(Component)]
-struct UpgradeButtonText {
-    text_type: UpgradeTextType,
+
+const MUTATORS: [Mutator; 4] = [
+    Mutator::GlassMushrooms,
+    Mutator::DoubleSpeedHero,
+    Mutator::UpgradeCap,
+    Mutator::SporeDecay,
+];
+
+impl Mutator {
+    fn name(&self) -> &'static str {
+        match self {
+            Mutator::GlassMushrooms => "Glass Mushrooms",
+            Mutator::DoubleSpeedHero => "Double-Speed Hero",
+            Mutator::UpgradeCap => "Upgrade Cap",
+            Mutator::SporeDecay => "Spore Decay",
+        }
+    }
+
+    fn apply(&self, modifiers: &mut RunModifiers) {
+        match self {
+            Mutator::GlassMushrooms => {
+                modifiers.mushroom_hp *= 0.5;
+                modifiers.mushroom_atk *= 2.0;
+            }
+            Mutator::DoubleSpeedHero => modifiers.hero_move_speed *= 2.0,
+            Mutator::UpgradeCap => modifiers.max_upgrade_level = Some(UPGRADE_CAP_LEVEL),
+            Mutator::SporeDecay => modifiers.spore_decay += SPORE_DECAY_RATE,
+        }
+    }
 }
 
-impl Copy for UpgradeButtonText {}
-impl Clone for UpgradeButtonText {
-    fn clone(&self) -> Self {
-        *self
+#[derive(Resource)]
+struct GameRng(StdRng);
+
+//Multipliers from the difficulty and mutators, applied to stats as units and buttons are made
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct RunModifiers {
+    hero_hp: f32,
+    hero_atk: f32,
+    hero_move_speed: f32,
+    mushroom_hp: f32,
+    mushroom_atk: f32,
+    spore_income: f32,
+    upgrade_cost: f32,
+    max_upgrade_level: Option<u32>,
+    //Fraction of banked spores lost per second
+    spore_decay: f32,
+}
+
+impl Default for RunModifiers {
+    fn default() -> Self {
+        RunModifiers {
+            hero_hp: 1.0,
+            hero_atk: 1.0,
+            hero_move_speed: 1.0,
+            mushroom_hp: 1.0,
+            mushroom_atk: 1.0,
+            spore_income: 1.0,
+            upgrade_cost: 1.0,
+            max_upgrade_level: None,
+            spore_decay: 0.0,
+        }
     }
 }
 
-#[derive(Component)]
-struct SummonButton;
+impl RunModifiers {
+    fn hero_stats(&self, stats: HeroStats) -> HeroStats {
+        HeroStats {
+            hp: stats.hp * self.hero_hp,
+            atk: stats.atk * self.hero_atk,
+            move_speed: stats.move_speed * self.hero_move_speed,
+            ..stats
+        }
+    }
 
-#[derive(Component)]
-struct MushroomBase;
+    fn mushroom(&self, mushroom: Mushroom) -> Mushroom {
+        Mushroom {
+            hp: mushroom.hp * self.mushroom_hp,
+            max_hp: mushroom.max_hp * self.mushroom_hp,
+            atk: mushroom.atk * self.mushroom_atk,
+            spore_count: self.spores(mushroom.spore_count),
+            ..mushroom
+        }
+    }
 
-#[derive(Component)]
-struct AttackTimer {
-    value: f32,
+    fn spores(&self, amount: i32) -> i32 {
+        ((amount as f32 * self.spore_income).round() as i32).max(1)
+    }
+
+    fn upgrade_cost(&self, cost: i32) -> i32 {
+        ((cost as f32 * self.upgrade_cost).round() as i32).max(1)
+    }
 }
 
-#[derive(Component)]
-struct InCombat {
-    value: bool,
+//Picked in the menus, read when a run starts
+#[derive(Resource, Default, Clone)]
+struct RunSettings {
+    mode: GameMode,
+    //Index into the campaign, only used in campaign mode
+    level: usize,
+    difficulty: Difficulty,
+    mutators: Vec<Mutator>,
+    //Every random roll in a run comes from this seed
+    seed: u64,
+    daily: Option<DailyChallenge>,
 }
 
-#[derive(Component)]
-struct Mushroom {
-    hp: f32,
-    atk: f32,
-    move_speed: f32,
-    atk_speed: f32,
-    atk_range: f32,
-    spore_count: i32,
-    xp_drop: f32,
+#[derive(Clone)]
+struct DailyChallenge {
+    date: String,
+    class: HeroClass,
+    starting_spores: i32,
 }
 
-impl Default for Mushroom {
-    fn default() -> Self {
-        Mushroom {
-            hp: MUSHROOM_BASE_HP,
-            atk: MUSHROOM_BASE_ATK,
-            move_speed: MUSHROOM_BASE_MOVE_SPEED,
-            atk_speed: MUSHROOM_BASE_ATK_SPEED,
-            atk_range: MUSHROOM_BASE_ATK_RANGE,
-            spore_count: MUSHROOM_BASE_SPORE_COUNT,
-            xp_drop: MUSHROOM_BASE_EXP_DROP,
+impl RunSettings {
+    fn daily(date: &str) -> Self {
+        let seed = fnv1a_hash(date);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let difficulty =
+            [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard][rng.gen_range(0..3)];
+        let mutators = MUTATORS
+            .into_iter()
+            .filter(|_| rng.gen_bool(DAILY_MUTATOR_CHANCE))
+            .collect();
+        let daily = DailyChallenge {
+            date: date.to_string(),
+            class: HERO_CLASS_LIST[rng.gen_range(0..HERO_CLASS_LIST.len())],
+            starting_spores: rng.gen_range(DAILY_MIN_STARTING_SPORES..=DAILY_MAX_STARTING_SPORES),
+        };
+
+        RunSettings {
+            mode: GameMode::Daily,
+            level: 0,
+            difficulty,
+            mutators,
+            seed,
+            daily: Some(daily),
+        }
+    }
+
+    fn modifiers(&self) -> RunModifiers {
+        let mut modifiers = self.difficulty.modifiers();
+        for mutator in &self.mutators {
+            mutator.apply(&mut modifiers);
+        }
+        modifiers
+    }
+
+    fn description(&self) -> String {
+        let mut description = format!("{:?}", self.difficulty);
+        for mutator in &self.mutators {
+            description += &format!(", {}", mutator.name());
+        }
+        description
+    }
+
+    fn campaign_level<'a>(&self, campaign: &'a Campaign) -> Option<&'a CampaignLevel> {
+        match self.mode {
+            GameMode::Campaign => campaign.levels.get(self.level),
+            _ => None,
         }
     }
 }
 
-impl Copy for Mushroom {}
-impl Clone for Mushroom {
-    fn clone(&self) -> Self {
-        *self
+#[derive(Clone, Copy, Deserialize)]
+enum WinCondition {
+    ClearAllWaves,
+    SurviveFor(f32),
+    KillHeroes(u32),
+    CollectSpores(i32),
+}
+
+impl WinCondition {
+    fn is_met(
+        &self,
+        game_manager: &GameManager,
+        encounter_manager: &EncounterManager,
+        spores: &Spores,
+    ) -> bool {
+        match *self {
+            WinCondition::ClearAllWaves => encounter_manager.finished,
+            WinCondition::SurviveFor(seconds) => game_manager.run_time >= seconds,
+            WinCondition::KillHeroes(count) => game_manager.heroes_killed >= count,
+            WinCondition::CollectSpores(count) => spores.count >= count,
+        }
+    }
+
+    fn description(&self) -> String {
+        match *self {
+            WinCondition::ClearAllWaves => "Clear all waves".to_string(),
+            WinCondition::SurviveFor(seconds) => format!("Survive for {seconds}s"),
+            WinCondition::KillHeroes(count) => format!("Kill {count} heroes"),
+            WinCondition::CollectSpores(count) => format!("Collect {count} spores"),
+        }
     }
 }
 
-#[derive(Component)]
-struct MushroomLord;
+#[derive(Deserialize)]
+struct CampaignLevel {
+    name: String,
+    starting_spores: i32,
+    background: String,
+    ground: String,
+    upgrades: Vec<UpgradeType>,
+    win_conditions: Vec<WinCondition>,
+    waves: Vec<Wave>,
+}
 
-#[derive(Eq, Hash, PartialEq)]
-enum UpgradeType {
-    SporeCount,
-    MushroomsPerClick,
-    HP,
-    ATK,
-    MoveSpeed,
-    AtkSpeed,
+#[derive(Resource, Deserialize)]
+struct Campaign {
+    levels: Vec<CampaignLevel>,
 }
 
-#[derive(Eq, Hash, PartialEq)]
-enum UpgradeTextType {
-    Value,
-    Cost,
+#[derive(Serialize, Deserialize)]
+struct LevelCompletion {
+    level: usize,
+    difficulty: Difficulty,
+    mutators: Vec<Mutator>,
 }
 
-impl Copy for UpgradeTextType {}
-impl Clone for UpgradeTextType {
-    fn clone(&self) -> Self {
-        *self
+#[derive(Default, Serialize, Deserialize)]
+struct CampaignProgress {
+    levels_completed: usize,
+    #[serde(default)]
+    completions: Vec<LevelCompletion>,
+}
+
+//A missing or broken save file just means starting fresh
+fn load_save_file<T: DeserializeOwned + Default>(path: &str) -> T {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| ron::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+//Saving is best effort, a failed write shouldn't interrupt the game
+fn write_save_file<T: Serialize>(path: &str, value: &T) {
+    if let Ok(contents) = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        let _ = std::fs::write(path, contents);
+    }
+}
+
+impl CampaignProgress {
+    fn load() -> Self {
+        load_save_file(CAMPAIGN_PROGRESS_FILE)
+    }
+
+    fn save(&self) {
+        write_save_file(CAMPAIGN_PROGRESS_FILE, self);
+    }
+
+    fn is_unlocked(&self, level: usize) -> bool {
+        level <= self.levels_completed
+    }
+
+    fn complete(&mut self, level: usize) {
+        self.levels_completed = self.levels_completed.max(level + 1);
+    }
+
+    fn record(&mut self, level: usize, run_settings: &RunSettings) {
+        self.complete(level);
+        self.completions.push(LevelCompletion {
+            level,
+            difficulty: run_settings.difficulty,
+            mutators: run_settings.mutators.clone(),
+        });
+    }
+
+    fn best_difficulty(&self, level: usize) -> Option<Difficulty> {
+        self.completions
+            .iter()
+            .filter(|completion| completion.level == level)
+            .map(|completion| completion.difficulty)
+            .max()
     }
 }
 
+//Top level entities spawned during a run, they all go when the run ends
 #[derive(Component)]
-struct MushroomManager {
-    mushroom_template: Mushroom,
-    spawn_count: i32,
+struct RunEntity;
+
+#[derive(Component)]
+struct GameManager {
+    game_over: bool,
+    victory: bool,
+    run_time: f32,
+    heroes_killed: u32,
+    peak_spores: i32,
+    score_recorded: bool,
+    //Any one of these wins the run
+    win_conditions: Vec<WinCondition>,
 }
 
-impl Default for MushroomManager {
+#[allow(clippy::derivable_impls)]
+impl Default for GameManager {
     fn default() -> Self {
-        MushroomManager {
-            mushroom_template: Mushroom::default(),
-            spawn_count: BASE_MUSHROOMS_PER_CLICK,
+        GameManager {
+            game_over: false,
+            victory: false,
+            run_time: 0.0,
+            heroes_killed: 0,
+            peak_spores: 0,
+            score_recorded: false,
+            win_conditions: Vec::new(),
         }
     }
 }
 
-#[derive(Component)]
-struct SummonManager {
-    is_summoned: bool,
+#[derive(Clone, Serialize, Deserialize)]
+struct HighScore {
+    mode: GameMode,
+    #[serde(default)]
+    difficulty: Difficulty,
+    #[serde(default)]
+    mutators: Vec<Mutator>,
+    waves_survived: usize,
+    peak_spores: i32,
+    time: f32,
 }
 
-impl Default for SummonManager {
-    fn default() -> Self {
-        SummonManager { is_summoned: false }
+impl HighScore {
+    //Best first: most waves, then longest run, then most spores
+    fn rank(a: &HighScore, b: &HighScore) -> std::cmp::Ordering {
+        b.waves_survived
+            .cmp(&a.waves_survived)
+            .then(b.time.total_cmp(&a.time))
+            .then(b.peak_spores.cmp(&a.peak_spores))
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct HighScoreTable {
+    scores: Vec<HighScore>,
+}
+
+impl HighScoreTable {
+    fn load() -> Self {
+        load_save_file(HIGH_SCORE_FILE)
+    }
+
+    fn save(&self) {
+        write_save_file(HIGH_SCORE_FILE, self);
+    }
+
+    fn insert(&mut self, score: HighScore) {
+        self.scores.push(score);
+        self.scores.sort_by(HighScore::rank);
+
+        let mut kept_per_mode: HashMap<GameMode, usize> = HashMap::new();
+        self.scores.retain(|score| {
+            let kept = kept_per_mode.entry(score.mode).or_insert(0);
+            *kept += 1;
+            *kept <= HIGH_SCORES_KEPT
+        });
+    }
+
+    fn top(&self, mode: GameMode) -> impl Iterator<Item = &HighScore> {
+        self.scores
+            .iter()
+            .filter(move |score| score.mode == mode)
+            .take(HIGH_SCORES_SHOWN)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct DailyScore {
+    date: String,
+    seed: u64,
+    score: HighScore,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct DailyLeaderboard {
+    scores: Vec<DailyScore>,
+}
+
+impl DailyLeaderboard {
+    fn load() -> Self {
+        load_save_file(DAILY_LEADERBOARD_FILE)
+    }
+
+    fn save(&self) {
+        write_save_file(DAILY_LEADERBOARD_FILE, self);
+    }
+
+    fn insert(&mut self, score: DailyScore) {
+        self.scores.push(score);
+        self.scores.sort_by(|a, b| {
+            b.date
+                .cmp(&a.date)
+                .then(HighScore::rank(&a.score, &b.score))
+        });
+
+        let mut kept_per_day: HashMap<String, usize> = HashMap::new();
+        self.scores.retain(|score| {
+            let kept = kept_per_day.entry(score.date.clone()).or_insert(0);
+            *kept += 1;
+            *kept <= HIGH_SCORES_KEPT
+        });
+    }
+
+    fn top<'a>(&'a self, date: &'a str) -> impl Iterator<Item = &'a DailyScore> {
+        self.scores
+            .iter()
+            .filter(move |score| score.date == date)
+            .take(HIGH_SCORES_SHOWN)
     }
 }
 
+//Days since 1970-01-01 to (year, month, day), Howard Hinnant's civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+//Days since 1970-01-01 in UTC, SystemTime panics on the web so the browser clock is used there
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_days() -> Option<i64> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|duration| (duration.as_secs() / 86400) as i64)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn unix_days() -> Option<i64> {
+    let millis = js_sys::Date::now();
+    (millis.is_finite() && millis >= 0.0).then(|| (millis / 86_400_000.0) as i64)
+}
+
+//Today's UTC date as YYYY-MM-DD, a broken clock falls back to the epoch rather than crashing
+fn today() -> String {
+    let (year, month, day) = civil_from_days(unix_days().unwrap_or(0));
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn fnv1a_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 #[derive(Component)]
-struct Ground;
+struct MenuUI;
 
 #[derive(Component)]
-struct Spores {
-    count: i32,
+struct ModeButton {
+    mode: GameMode,
 }
 
 #[derive(Component)]
-struct Hero {
-    hp: f32,
-    atk: f32,
-    move_speed: f32,
-    atk_speed: f32,
-    atk_range: f32,
-    level: i32,
-    exp: f32,
-    next_level_exp: f32,
-}
+struct BackToMenuButton;
 
 #[derive(Component)]
-struct SporeText;
+struct LevelSelectUI;
 
 #[derive(Component)]
-struct HeroHPText;
+struct LevelButton {
+    level: usize,
+    unlocked: bool,
+}
 
 #[derive(Component)]
-struct HeroEXPText;
+struct ObjectiveText;
 
 #[derive(Component)]
-struct HeroLevelText;
+struct RunSetupUI;
 
 #[derive(Component)]
-struct MushroomLordHPText;
+struct DifficultyButton {
+    difficulty: Difficulty,
+}
 
 #[derive(Component)]
-struct MushroomLordUI;
+struct MutatorButton {
+    mutator: Mutator,
+}
 
-fn load_assets_system(mut image_manager: ResMut<ImageManager>, asset_server: Res<AssetServer>) {
-    let mushroom_sprite_asset: Handle<Image> = asset_server.load("./boi.png");
-    let mushroom_base_sprite_asset: Handle<Image> = asset_server.load("./base.png");
-    let ground_sprite_asset: Handle<Image> = asset_server.load("./ground.png");
-    let hero_sprite_asset: Handle<Image> = asset_server.load("./hero.png");
+#[derive(Component)]
+struct StartRunButton;
 
-    image_manager.images.insert(
-        ImageType::Mushroom,
-        SpriteImage {
-            image_handle: mushroom_sprite_asset,
-        },
-    );
+#[derive(Component)]
+struct HighScoreText;
 
-    image_manager.images.insert(
-        ImageType::MushroomBase,
-        SpriteImage {
-            image_handle: mushroom_base_sprite_asset,
-        },
-    );
+#[derive(Component)]
+struct HeroAttackAudio;
 
-    image_manager.images.insert(
-        ImageType::Ground,
-        SpriteImage {
-            image_handle: ground_sprite_asset,
-        },
-    );
+#[derive(Component)]
+struct GameCamera;
 
-    image_manager.images.insert(
-        ImageType::Hero,
-        SpriteImage {
-            image_handle: hero_sprite_asset,
-        },
-    );
+#[derive(Component)]
+struct UpgradeButton {
+    upgrade_type: UpgradeType,
+    cost: i32,
+    cost_multiplier: i32,
+    level: u32,
+    max_level: Option<u32>,
+}
+#[derive(Component)]
+struct UpgradeButtonText {
+    text_type: UpgradeTextType,
+}
 
-    image_manager.images.insert(
-        ImageType::HeroAttack,
-        SpriteImage {
-            image_handle: asset_server.load("./hero_attack.png"),
-        },
-    );
+impl Copy for UpgradeButtonText {}
+impl Clone for UpgradeButtonText {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
 
-    image_manager.images.insert(
-        ImageType::Background,
-        SpriteImage {
-            image_handle: asset_server.load("./background.png"),
-        },
-    );
+#[derive(Component)]
+struct SummonButton;
+
+#[derive(Component)]
+struct MushroomBase {
+    hp: f32,
+    max_hp: f32,
+    regen: f32,
+    armor: f32,
+    toxic_resistance: f32,
 }
 
-fn setup_ui_system(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let font_handle = asset_server.load("./fonts/Roboto-Regular.ttf");
-    let summoning_circle_image = asset_server.load("./summon_circle.png");
+impl MushroomBase {
+    fn defense(&self) -> Defense {
+        Defense {
+            armor: self.armor,
+            toxic_resistance: self.toxic_resistance,
+        }
+    }
+}
 
-    //Game Over
-    commands
-        .spawn((
-            NodeBundle {
-                style: Style {
-                    width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
-                    align_items: AlignItems::Center,
-                    justify_content: JustifyContent::Center,
-                    ..default()
-                },
-                background_color: BackgroundColor(Color::BLACK),
-                z_index: ZIndex::Global(999),
-                visibility: Visibility::Hidden,
-                ..default()
-            },
-            GameOverUI,
-        ))
-        .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    "GAME OVER",
-                    TextStyle {
-                        font: font_handle.clone(),
-                        font_size: 40.0,
-                        // Alpha channel of the color controls transparency.
-                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-                    },
-                ),
-                GameOverText,
-            ));
-        });
+impl Default for MushroomBase {
+    fn default() -> Self {
+        MushroomBase {
+            hp: BASE_STRUCTURE_HP,
+            max_hp: BASE_STRUCTURE_HP,
+            regen: BASE_STRUCTURE_REGEN,
+            armor: BASE_STRUCTURE_ARMOR,
+            toxic_resistance: BASE_STRUCTURE_TOXIC_RESISTANCE,
+        }
+    }
+}
 
-    //Spores
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(20.0),
-                height: Val::Percent(10.0),
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::Start,
-                left: Val::Percent(20.0),
-                top: Val::Percent(5.0),
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    "Spores:",
-                    TextStyle {
-                        font: font_handle.clone(),
-                        font_size: 40.0,
-                        // Alpha channel of the color controls transparency.
-                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-                    },
-                ),
-                SporeText,
-            ));
-        });
+//Background sprite of a world-space health bar, the fill is its only child
+#[derive(Component)]
+struct HealthBar {
+    width: f32,
+    always_shown: bool,
+}
 
-    //Mushroom Lord
-    commands
+impl HealthBar {
+    fn fraction(hp: f32, max_hp: f32) -> f32 {
+        if max_hp <= 0.0 {
+            return 0.0;
+        }
+        (hp / max_hp).clamp(0.0, 1.0)
+    }
+
+    fn is_shown(&self, fraction: f32, show_full: bool) -> bool {
+        self.always_shown || (fraction > 0.0 && (fraction < 1.0 || show_full))
+    }
+}
+
+#[derive(Component)]
+struct HealthBarFill;
+
+fn spawn_health_bar(parent: &mut ChildBuilder, size: Vec2, offset_y: f32, always_shown: bool) {
+    parent
         .spawn((
-            NodeBundle {
-                style: Style {
-                    width: Val::Percent(30.0),
-                    height: Val::Percent(20.0),
-                    display: Display::Flex,
-                    flex_direction: FlexDirection::Column,
-                    align_items: AlignItems::Start,
-                    justify_content: JustifyContent::Start,
-                    top: Val::Percent(10.0),
-                    left: Val::Percent(20.0),
+            SpriteBundle {
+                sprite: Sprite {
+                    color: HEALTH_BAR_BACKGROUND_COLOR,
+                    custom_size: Some(size),
                     ..default()
                 },
-                visibility: Visibility::Hidden,
+                transform: Transform::from_xyz(0.0, offset_y, 1.0),
+                visibility: if always_shown {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                },
                 ..default()
             },
-            MushroomLordUI,
+            HealthBar {
+                width: size.x,
+                always_shown,
+            },
         ))
         .with_children(|parent| {
             parent.spawn((
-                TextBundle::from_section(
-                    "Mushroom Lord HP:",
-                    TextStyle {
-                        font: font_handle.clone(),
-                        font_size: 20.0,
-                        // Alpha channel of the color controls transparency.
-                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: HEALTH_BAR_FILL_COLOR,
+                        custom_size: Some(size),
+                        ..default()
                     },
-                ),
-                MushroomLordHPText,
+                    transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                    ..default()
+                },
+                HealthBarFill,
             ));
         });
+}
 
-    //Hero stuff
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(30.0),
-                height: Val::Percent(20.0),
-                display: Display::Flex,
-                flex_direction: FlexDirection::Column,
-                align_items: AlignItems::Start,
-                justify_content: JustifyContent::Start,
-                top: Val::Percent(5.0),
-                left: Val::Percent(80.0),
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    "Hero HP:",
-                    TextStyle {
-                        font: font_handle.clone(),
-                        font_size: 20.0,
-                        // Alpha channel of the color controls transparency.
-                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-                    },
-                ),
-                HeroHPText,
-            ));
-        })
-        .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    "Hero EXP:",
-                    TextStyle {
-                        font: font_handle.clone(),
-                        font_size: 20.0,
-                        // Alpha channel of the color controls transparency.
-                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-                    },
-                ),
-                HeroEXPText,
-            ));
-        })
-        .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    "Hero Level:",
-                    TextStyle {
-                        font: font_handle.clone(),
-                        font_size: 20.0,
-                        // Alpha channel of the color controls transparency.
-                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
-                    },
-                ),
-                HeroLevelText,
-            ));
-        });
+fn spawn_unit_health_bar(parent: &mut ChildBuilder) {
+    spawn_health_bar(
+        parent,
+        Vec2::new(UNIT_HEALTH_BAR_WIDTH, UNIT_HEALTH_BAR_HEIGHT),
+        UNIT_HEALTH_BAR_OFFSET_Y,
+        false,
+    );
+}
 
-    let upgrade_button_text_style = TextStyle {
-        font: asset_server.load("./fonts/Roboto-Regular.ttf"),
-        font_size: 24.0,
-        color: Color::BLACK,
-    };
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum UnitState {
+    //Just arrived, does nothing until the timer runs out
+    Spawning { remaining: f32 },
+    //Moving down the lane with nothing in range
+    Advancing,
+    //Something is in range, waiting for the attack cooldown
+    Engaging,
+    //Mid swing, the hit lands on the impact frame of the attack clip
+    Attacking,
+    Stunned,
+    //Out of hp, nothing gets it out of this state
+    Dying,
+}
 
-    let get_cost_button_bundle = |cost| {
-        (
-            TextBundle::from_section(format!("Cost: {cost}"), upgrade_button_text_style.clone()),
-            UpgradeButtonText {
-                text_type: UpgradeTextType::Cost,
-            },
-        )
-    };
+//Everything a unit's state depends on, gathered by the unit's attack system each frame
+#[derive(Default)]
+struct UnitSenses {
+    target_in_range: bool,
+    stunned: bool,
+    dead: bool,
+    //Casting or anything else that stops a new attack from starting
+    busy: bool,
+    attack_finished: bool,
+}
 
-    let upgrade_button_font_type = asset_server.load("./fonts/Roboto-Regular.ttf");
-    let create_upgrade_button =
-        |width, height, upgrade_type, cost, cost_multiplier, text, font_type| {
-            return move |parent: &mut ChildBuilder| {
-                parent
-                    .spawn((
-                        ButtonBundle {
-                            style: Style {
-                                width: Val::Px(width),
-                                height: Val::Px(height),
-                                border: UiRect::all(Val::Px(2.0)),
-                                flex_direction: FlexDirection::Column,
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                ..default()
-                            },
-                            border_color: BorderColor(Color::BLACK),
-                            background_color: NORMAL_BUTTON.into(),
-                            ..default()
-                        },
-                        UpgradeButton {
-                            upgrade_type: upgrade_type,
-                            cost: cost,
-                            cost_multiplier: cost_multiplier,
-                        },
-                    ))
-                    .with_children(|parent| {
-                        parent.spawn((
-                            TextBundle::from_section(
-                                text,
-                                TextStyle {
-                                    font: font_type,
-                                    font_size: 24.0,
-                                    color: Color::BLACK,
-                                },
-                            ),
-                            UpgradeButtonText {
-                                text_type: UpgradeTextType::Value,
-                            },
-                        ));
-                    })
-                    .with_children(|parent| {
-                        parent.spawn(get_cost_button_bundle(cost));
-                    });
-            };
-        };
+//Added when a unit dies, the entity goes away once the die clip and fade are done
+#[derive(Component, Default)]
+struct DeathSequence {
+    elapsed: f32,
+}
 
-    //Upgrades
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(100.0),
-                height: Val::Percent(100.0),
-                padding: UiRect {
-                    left: Val::Px(20.0),
-                    right: Val::Px(20.0),
-                    ..Default::default()
-                },
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::SpaceBetween,
-                left: Val::Percent(0.0),
-                top: Val::Percent(20.0),
-                ..default()
+//Spores dropped by a dead mushroom, they count once they reach the spore counter
+#[derive(Component)]
+struct SporeParticle {
+    amount: i32,
+    velocity: Vec2,
+    elapsed: f32,
+}
+
+#[derive(Component)]
+struct Unit {
+    state: UnitState,
+    attack_cooldown: f32,
+}
+
+impl Unit {
+    fn new(spawn_time: f32) -> Self {
+        Unit {
+            state: UnitState::Spawning {
+                remaining: spawn_time,
             },
-            ..default()
-        })
+            attack_cooldown: 0.0,
+        }
+    }
+
+    fn die(&mut self) {
+        self.state = UnitState::Dying;
+    }
+
+    fn is_engaged(&self) -> bool {
+        matches!(self.state, UnitState::Engaging | UnitState::Attacking)
+    }
+
+    //Returns the new state when the unit changes state, the caller starts the attack on Attacking
+    fn update(&mut self, senses: &UnitSenses, delta: f32) -> Option<UnitState> {
+        self.attack_cooldown -= delta;
+        let engaged_or_advancing = if senses.target_in_range {
+            UnitState::Engaging
+        } else {
+            UnitState::Advancing
+        };
+
+        let next = match self.state {
+            UnitState::Dying => UnitState::Dying,
+            _ if senses.dead => UnitState::Dying,
+            UnitState::Spawning { remaining } if remaining > delta => UnitState::Spawning {
+                remaining: remaining - delta,
+            },
+            _ if senses.stunned => UnitState::Stunned,
+            UnitState::Attacking if !senses.attack_finished => UnitState::Attacking,
+            UnitState::Engaging
+                if senses.target_in_range && self.attack_cooldown <= 0.0 && !senses.busy =>
+            {
+                UnitState::Attacking
+            }
+            _ => engaged_or_advancing,
+        };
+
+        let previous = std::mem::replace(&mut self.state, next);
+        let changed = std::mem::discriminant(&previous) != std::mem::discriminant(&next);
+        changed.then_some(next)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+enum DamageType {
+    Physical,
+    //Spore damage, reduced by toxic resistance
+    Toxic,
+    //Ignores armor and resistances
+    True,
+}
+
+struct Attack {
+    amount: f32,
+    damage_type: DamageType,
+    crit_chance: f32,
+    crit_multiplier: f32,
+}
+
+struct Defense {
+    armor: f32,
+    toxic_resistance: f32,
+}
+
+#[derive(Debug, PartialEq)]
+struct DamageResult {
+    amount: f32,
+    is_crit: bool,
+}
+
+//Sent for every hit that lands, the feedback systems turn these into numbers, flashes and shake
+#[derive(Event)]
+struct DamageEvent {
+    target: Entity,
+    position: Vec3,
+    damage_type: DamageType,
+    amount: f32,
+    is_crit: bool,
+}
+
+impl DamageEvent {
+    fn new(target: Entity, position: Vec3, attack: &Attack, damage: &DamageResult) -> Self {
+        DamageEvent {
+            target,
+            position,
+            damage_type: attack.damage_type,
+            amount: damage.amount,
+            is_crit: damage.is_crit,
+        }
+    }
+
+    fn color(&self) -> Color {
+        if self.is_crit {
+            return CRIT_DAMAGE_COLOR;
+        }
+        match self.damage_type {
+            DamageType::Physical => PHYSICAL_DAMAGE_COLOR,
+            DamageType::Toxic => TOXIC_DAMAGE_COLOR,
+            DamageType::True => TRUE_DAMAGE_COLOR,
+        }
+    }
+
+    fn text(&self) -> String {
+        let amount = self.amount.round();
+        if self.is_crit {
+            format!("{amount}!")
+        } else {
+            format!("{amount}")
+        }
+    }
+}
+
+#[derive(Component)]
+struct FloatingText {
+    elapsed: f32,
+}
+
+#[derive(Component, Default)]
+struct HitFlash {
+    remaining: f32,
+}
+
+//Lives on the camera, trauma goes up with big hits and wears off over time
+#[derive(Component, Default)]
+struct ScreenShake {
+    trauma: f32,
+}
+
+impl ScreenShake {
+    fn add(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    damage_numbers: bool,
+    hit_flash: bool,
+    screen_shake: bool,
+    //Off hides unit health bars until the unit takes damage
+    full_health_bars: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            damage_numbers: true,
+            hit_flash: true,
+            screen_shake: true,
+            full_health_bars: false,
+        }
+    }
+}
+
+impl Settings {
+    fn load() -> Self {
+        load_save_file(SETTINGS_FILE)
+    }
+
+    fn save(&self) {
+        write_save_file(SETTINGS_FILE, self);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Setting {
+    DamageNumbers,
+    HitFlash,
+    ScreenShake,
+    FullHealthBars,
+}
+
+impl Setting {
+    fn value(self, settings: &Settings) -> bool {
+        match self {
+            Setting::DamageNumbers => settings.damage_numbers,
+            Setting::HitFlash => settings.hit_flash,
+            Setting::ScreenShake => settings.screen_shake,
+            Setting::FullHealthBars => settings.full_health_bars,
+        }
+    }
+
+    fn value_mut(self, settings: &mut Settings) -> &mut bool {
+        match self {
+            Setting::DamageNumbers => &mut settings.damage_numbers,
+            Setting::HitFlash => &mut settings.hit_flash,
+            Setting::ScreenShake => &mut settings.screen_shake,
+            Setting::FullHealthBars => &mut settings.full_health_bars,
+        }
+    }
+
+    fn label(self, settings: &Settings) -> String {
+        let name = match self {
+            Setting::DamageNumbers => "Numbers",
+            Setting::HitFlash => "Hit flash",
+            Setting::ScreenShake => "Shake",
+            Setting::FullHealthBars => "Full HP bars",
+        };
+        let state = if self.value(settings) { "On" } else { "Off" };
+        format!("{name}: {state}")
+    }
+}
+
+#[derive(Component)]
+struct SettingButton {
+    setting: Setting,
+}
+
+fn resolve_damage(attack: &Attack, defense: &Defense, rng: &mut impl Rng) -> DamageResult {
+    let is_crit = rng.gen::<f32>() < attack.crit_chance;
+    let mut amount = attack.amount;
+    if is_crit {
+        amount *= attack.crit_multiplier;
+    }
+
+    amount *= match attack.damage_type {
+        DamageType::Physical => {
+            ARMOR_HALF_DAMAGE_POINT / (ARMOR_HALF_DAMAGE_POINT + defense.armor.max(0.0))
+        }
+        DamageType::Toxic => 1.0 - defense.toxic_resistance.clamp(0.0, MAX_RESISTANCE),
+        DamageType::True => 1.0,
+    };
+
+    DamageResult { amount, is_crit }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+enum StatusEffectKind {
+    Poison,
+    Slow,
+    Stun,
+    Rage,
+    Haste,
+}
+
+enum StackingRule {
+    //Each application adds a stack up to the limit and refreshes the duration
+    Intensity { max_stacks: i32 },
+    //Keeps the strongest magnitude and refreshes the duration
+    Refresh,
+    //Keeps whichever duration is longer, so chaining can't extend it forever
+    KeepLongest,
+}
+
+impl StatusEffectKind {
+    fn stacking_rule(&self) -> StackingRule {
+        match self {
+            StatusEffectKind::Poison => StackingRule::Intensity {
+                max_stacks: POISON_MAX_STACKS,
+            },
+            StatusEffectKind::Slow => StackingRule::Refresh,
+            StatusEffectKind::Stun => StackingRule::KeepLongest,
+            StatusEffectKind::Rage => StackingRule::Refresh,
+            StatusEffectKind::Haste => StackingRule::Refresh,
+        }
+    }
+
+    fn tint(&self) -> Color {
+        match self {
+            StatusEffectKind::Poison => POISON_TINT,
+            StatusEffectKind::Slow => SLOW_TINT,
+            StatusEffectKind::Stun => STUN_TINT,
+            StatusEffectKind::Rage => RAGE_TINT,
+            StatusEffectKind::Haste => HASTE_TINT,
+        }
+    }
+}
+
+//magnitude is damage per second per stack for poison, and a fraction for the rest
+#[derive(Clone, Copy, Deserialize)]
+struct StatusEffect {
+    kind: StatusEffectKind,
+    magnitude: f32,
+    duration: f32,
+}
+
+struct ActiveStatusEffect {
+    effect: StatusEffect,
+    remaining: f32,
+    stacks: i32,
+    tick_timer: f32,
+}
+
+#[derive(Component, Default)]
+struct StatusEffects {
+    active: Vec<ActiveStatusEffect>,
+}
+
+impl StatusEffects {
+    fn apply(&mut self, effect: StatusEffect) {
+        let existing = self
+            .active
+            .iter_mut()
+            .find(|active| active.effect.kind == effect.kind);
+
+        let Some(active) = existing else {
+            self.active.push(ActiveStatusEffect {
+                effect,
+                remaining: effect.duration,
+                stacks: 1,
+                tick_timer: STATUS_EFFECT_TICK_INTERVAL,
+            });
+            return;
+        };
+
+        match effect.kind.stacking_rule() {
+            StackingRule::Intensity { max_stacks } => {
+                active.stacks = (active.stacks + 1).min(max_stacks);
+                active.effect.magnitude = active.effect.magnitude.max(effect.magnitude);
+                active.remaining = effect.duration;
+            }
+            StackingRule::Refresh => {
+                active.effect.magnitude = active.effect.magnitude.max(effect.magnitude);
+                active.remaining = effect.duration;
+            }
+            StackingRule::KeepLongest => {
+                active.remaining = active.remaining.max(effect.duration);
+            }
+        }
+    }
+
+    fn magnitude(&self, kind: StatusEffectKind) -> f32 {
+        self.active
+            .iter()
+            .find(|active| active.effect.kind == kind)
+            .map(|active| active.effect.magnitude * active.stacks as f32)
+            .unwrap_or(0.0)
+    }
+
+    fn is_stunned(&self) -> bool {
+        self.active
+            .iter()
+            .any(|active| active.effect.kind == StatusEffectKind::Stun)
+    }
+
+    fn move_speed_multiplier(&self) -> f32 {
+        if self.is_stunned() {
+            return 0.0;
+        }
+        (1.0 - self.magnitude(StatusEffectKind::Slow)).max(0.0)
+            * (1.0 + self.magnitude(StatusEffectKind::Haste))
+    }
+
+    fn atk_multiplier(&self) -> f32 {
+        1.0 + self.magnitude(StatusEffectKind::Rage)
+    }
+
+    fn atk_speed_multiplier(&self) -> f32 {
+        1.0 + self.magnitude(StatusEffectKind::Haste)
+    }
+
+    //The most recently applied effect wins the tint
+    fn tint(&self) -> Option<Color> {
+        self.active.last().map(|active| active.effect.kind.tint())
+    }
+}
+
+#[derive(Component)]
+struct Mushroom {
+    hp: f32,
+    max_hp: f32,
+    atk: f32,
+    move_speed: f32,
+    atk_speed: f32,
+    atk_range: f32,
+    spore_count: i32,
+    xp_drop: f32,
+    on_hit_effect: Option<StatusEffect>,
+    damage_type: DamageType,
+    armor: f32,
+    toxic_resistance: f32,
+    crit_chance: f32,
+    crit_multiplier: f32,
+    //Seconds each one spends in the spawn queue
+    build_time: f32,
+}
+
+impl Mushroom {
+    fn attack(&self, amount: f32) -> Attack {
+        Attack {
+            amount,
+            damage_type: self.damage_type,
+            crit_chance: self.crit_chance,
+            crit_multiplier: self.crit_multiplier,
+        }
+    }
+
+    fn defense(&self) -> Defense {
+        Defense {
+            armor: self.armor,
+            toxic_resistance: self.toxic_resistance,
+        }
+    }
+}
+
+impl Default for Mushroom {
+    fn default() -> Self {
+        Mushroom {
+            hp: MUSHROOM_BASE_HP,
+            max_hp: MUSHROOM_BASE_HP,
+            atk: MUSHROOM_BASE_ATK,
+            move_speed: MUSHROOM_BASE_MOVE_SPEED,
+            atk_speed: MUSHROOM_BASE_ATK_SPEED,
+            atk_range: MUSHROOM_BASE_ATK_RANGE,
+            spore_count: MUSHROOM_BASE_SPORE_COUNT,
+            xp_drop: MUSHROOM_BASE_EXP_DROP,
+            on_hit_effect: Some(MUSHROOM_ON_HIT_EFFECT),
+            damage_type: DamageType::Toxic,
+            armor: MUSHROOM_BASE_ARMOR,
+            toxic_resistance: MUSHROOM_BASE_TOXIC_RESISTANCE,
+            crit_chance: MUSHROOM_BASE_CRIT_CHANCE,
+            crit_multiplier: MUSHROOM_BASE_CRIT_MULTIPLIER,
+            build_time: MUSHROOM_BUILD_TIME,
+        }
+    }
+}
+
+impl Copy for Mushroom {}
+impl Clone for Mushroom {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[derive(Component)]
+struct MushroomLord {
+    tier: usize,
+}
+
+struct MushroomLordTier {
+    name: &'static str,
+    required_spores_sacrificed: i32,
+    stat_multiplier: f32,
+    scale: f32,
+    tint: Color,
+}
+
+fn mushroom_lord_stats(tier: &MushroomLordTier, spores_sacrificed: i32) -> Mushroom {
+    let spores = spores_sacrificed as f32;
+    let hp = (MUSHROOM_LORD_BASE_HP
+        + MUSHROOM_LORD_BASE_HP * MUSHROOM_LORD_SPORE_MULTIPLIER_HP * spores)
+        * tier.stat_multiplier;
+
+    Mushroom {
+        hp,
+        max_hp: hp,
+        atk: (MUSHROOM_LORD_BASE_ATK
+            + MUSHROOM_LORD_BASE_ATK * MUSHROOM_LORD_SPORE_MULTIPLIER_ATK * spores)
+            * tier.stat_multiplier,
+        move_speed: MUSHROOM_LORD_BASE_MOVE_SPEED
+            + MUSHROOM_LORD_BASE_MOVE_SPEED * MUSHROOM_LORD_SPORE_MULTIPLIER_MOVE_SPEED * spores,
+        atk_speed: MUSHROOM_LORD_BASE_ATK_SPEED
+            + MUSHROOM_LORD_BASE_ATK_SPEED * MUSHROOM_LORD_SPORE_MULTIPLIER_ATK_SPEED * spores,
+        atk_range: MUSHROOM_LORD_BASE_ATK_RANGE
+            + MUSHROOM_LORD_BASE_ATK_RANGE * MUSHROOM_LORD_SPORE_MULTIPLIER_ATK_RANGE * spores,
+        spore_count: MUSHROOM_LORD_BASE_SPORE_COUNT,
+        xp_drop: MUSHROOM_LORD_BASE_EXP_DROP,
+        on_hit_effect: Some(MUSHROOM_LORD_ON_HIT_EFFECT),
+        damage_type: DamageType::True,
+        armor: MUSHROOM_LORD_BASE_ARMOR * tier.stat_multiplier,
+        toxic_resistance: MUSHROOM_LORD_BASE_TOXIC_RESISTANCE,
+        crit_chance: MUSHROOM_LORD_BASE_CRIT_CHANCE,
+        crit_multiplier: MUSHROOM_LORD_BASE_CRIT_MULTIPLIER,
+        build_time: MUSHROOM_LORD_BUILD_TIME,
+    }
+}
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+enum LordAbility {
+    SporeNova,
+    Rally,
+    Root,
+    SpawnMinions,
+}
+
+const LORD_ABILITIES: [LordAbility; 4] = [
+    LordAbility::SporeNova,
+    LordAbility::Rally,
+    LordAbility::Root,
+    LordAbility::SpawnMinions,
+];
+
+impl LordAbility {
+    fn name(&self) -> &'static str {
+        match self {
+            LordAbility::SporeNova => "Spore Nova",
+            LordAbility::Rally => "Rally",
+            LordAbility::Root => "Root",
+            LordAbility::SpawnMinions => "Spawn Minions",
+        }
+    }
+
+    fn key(&self) -> KeyCode {
+        match self {
+            LordAbility::SporeNova => SPORE_NOVA_KEY,
+            LordAbility::Rally => RALLY_KEY,
+            LordAbility::Root => ROOT_KEY,
+            LordAbility::SpawnMinions => SPAWN_MINIONS_KEY,
+        }
+    }
+
+    fn cooldown(&self) -> f32 {
+        match self {
+            LordAbility::SporeNova => SPORE_NOVA_COOLDOWN,
+            LordAbility::Rally => RALLY_COOLDOWN,
+            LordAbility::Root => ROOT_COOLDOWN,
+            LordAbility::SpawnMinions => SPAWN_MINIONS_COOLDOWN,
+        }
+    }
+}
+
+#[derive(Component)]
+struct LordAbilities {
+    cooldowns: HashMap<LordAbility, f32>,
+    auto_cast: bool,
+    //Pressed since the last fixed tick
+    requested: Vec<LordAbility>,
+}
+
+impl Default for LordAbilities {
+    fn default() -> Self {
+        LordAbilities {
+            cooldowns: LORD_ABILITIES
+                .iter()
+                .map(|&ability| (ability, 0.0))
+                .collect(),
+            auto_cast: false,
+            requested: Vec::new(),
+        }
+    }
+}
+
+impl LordAbilities {
+    fn cooldown(&self, ability: LordAbility) -> f32 {
+        self.cooldowns.get(&ability).copied().unwrap_or(0.0)
+    }
+}
+
+//Colour a sprite returns to when no status effect is showing
+#[derive(Component)]
+struct SpriteTint {
+    color: Color,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+enum AnimationSheet {
+    Hero,
+    Mushroom,
+    MushroomLord,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+enum AnimationClip {
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    Die,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+enum FrameEvent {
+    Impact,
+    Finished,
+}
+
+#[derive(Deserialize)]
+struct ClipDefinition {
+    first: usize,
+    frames: usize,
+    fps: f32,
+    looping: bool,
+    #[serde(default)]
+    events: Vec<(usize, FrameEvent)>,
+}
+
+impl ClipDefinition {
+    fn duration(&self) -> f32 {
+        self.frames as f32 / self.fps
+    }
+
+    fn events_on(&self, frame: usize) -> impl Iterator<Item = FrameEvent> + '_ {
+        self.events
+            .iter()
+            .filter(move |(event_frame, _)| *event_frame == frame)
+            .map(|(_, event)| *event)
+    }
+}
+
+#[derive(Deserialize)]
+struct SheetDefinition {
+    texture: String,
+    tile_size: (f32, f32),
+    columns: usize,
+    rows: usize,
+    clips: HashMap<AnimationClip, ClipDefinition>,
+}
+
+#[derive(Resource, Deserialize)]
+struct AnimationManager {
+    sheets: HashMap<AnimationSheet, SheetDefinition>,
+    //Filled in by load_assets_system
+    #[serde(skip)]
+    atlases: HashMap<AnimationSheet, Handle<TextureAtlas>>,
+}
+
+impl AnimationManager {
+    fn clip(&self, sheet: AnimationSheet, clip: AnimationClip) -> &ClipDefinition {
+        &self.sheets[&sheet].clips[&clip]
+    }
+
+    fn sprite_sheet(
+        &self,
+        sheet: AnimationSheet,
+        transform: Transform,
+        color: Color,
+    ) -> (SpriteSheetBundle, Animation) {
+        (
+            SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index: self.clip(sheet, AnimationClip::Idle).first,
+                    color,
+                    ..default()
+                },
+                texture_atlas: self.atlases[&sheet].clone_weak(),
+                transform,
+                ..default()
+            },
+            Animation::new(sheet),
+        )
+    }
+}
+
+#[derive(Component)]
+struct Animation {
+    sheet: AnimationSheet,
+    clip: AnimationClip,
+    frame: usize,
+    timer: f32,
+    //Playback rate, attacks speed up to fit inside short cooldowns
+    speed: f32,
+    started: bool,
+    finished: bool,
+}
+
+impl Animation {
+    fn new(sheet: AnimationSheet) -> Self {
+        Animation {
+            sheet,
+            clip: AnimationClip::Idle,
+            frame: 0,
+            timer: 0.0,
+            speed: 1.0,
+            started: false,
+            finished: false,
+        }
+    }
+
+    //Keeps going if the clip is already playing
+    fn play(&mut self, clip: AnimationClip) {
+        if self.clip != clip {
+            self.restart(clip, 1.0);
+        }
+    }
+
+    fn restart(&mut self, clip: AnimationClip, speed: f32) {
+        self.clip = clip;
+        self.frame = 0;
+        self.timer = 0.0;
+        self.speed = speed;
+        self.started = false;
+        self.finished = false;
+    }
+
+    fn is_playing(&self, clip: AnimationClip) -> bool {
+        self.clip == clip && !self.finished
+    }
+
+    //Steps through the clip and returns the events of every frame reached on the way
+    fn advance(&mut self, clip: &ClipDefinition, delta: f32) -> Vec<FrameEvent> {
+        let mut events = Vec::new();
+        if !self.started {
+            self.started = true;
+            events.extend(clip.events_on(0));
+        }
+
+        if !self.finished {
+            self.timer += delta * self.speed;
+        }
+        let frame_time = 1.0 / clip.fps;
+        while self.timer >= frame_time && !self.finished {
+            self.timer -= frame_time;
+            if self.frame + 1 < clip.frames {
+                self.frame += 1;
+                events.extend(clip.events_on(self.frame));
+            } else if clip.looping {
+                self.frame = 0;
+                events.extend(clip.events_on(0));
+            } else {
+                self.finished = true;
+                events.push(FrameEvent::Finished);
+            }
+        }
+        events
+    }
+
+    fn atlas_index(&self, clip: &ClipDefinition) -> usize {
+        clip.first + self.frame
+    }
+}
+
+#[derive(Event)]
+struct AnimationEvent {
+    entity: Entity,
+    event: FrameEvent,
+}
+
+#[derive(Eq, Hash, PartialEq, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+enum UpgradeType {
+    SporeCount,
+    MushroomsPerClick,
+    HP,
+    ATK,
+    MoveSpeed,
+    AtkSpeed,
+    CritChance,
+    CritMultiplier,
+    BaseWalls,
+    BaseRegen,
+    PopulationCap,
+}
+
+#[derive(Eq, Hash, PartialEq)]
+enum UpgradeTextType {
+    Value,
+    Cost,
+}
+
+impl Copy for UpgradeTextType {}
+impl Clone for UpgradeTextType {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+//A batch of mushrooms waiting to grow, spores are paid and stats fixed when it is queued
+#[derive(Clone, Copy)]
+struct SpawnOrder {
+    lane: usize,
+    count: i32,
+    formation: Formation,
+    mushroom: Mushroom,
+    //Set when the batch is a mushroom lord of this tier
+    lord_tier: Option<usize>,
+    elapsed: f32,
+}
+
+impl SpawnOrder {
+    fn new(lane: usize, count: i32, formation: Formation, mushroom: Mushroom) -> Self {
+        SpawnOrder {
+            lane,
+            count,
+            formation,
+            mushroom,
+            lord_tier: None,
+            elapsed: 0.0,
+        }
+    }
+
+    //The lord comes out next to the base, on the first lane
+    fn lord(tier: usize, mushroom: Mushroom) -> Self {
+        SpawnOrder {
+            lord_tier: Some(tier),
+            ..SpawnOrder::new(0, 1, Formation::default(), mushroom)
+        }
+    }
+
+    fn build_time(&self) -> f32 {
+        self.count as f32 * self.mushroom.build_time
+    }
+
+    fn progress(&self) -> f32 {
+        (self.elapsed / self.build_time()).min(1.0)
+    }
+}
+
+//Parked for spawn_mushroom to reuse, only the transform and health bar are left
+#[derive(Component)]
+struct Pooled;
+
+#[derive(Component)]
+struct MushroomManager {
+    mushroom_template: Mushroom,
+    spawn_count: i32,
+    formation: Formation,
+    pool: EntityPool,
+    queue: VecDeque<SpawnOrder>,
+    population_cap: i32,
+}
+
+impl Default for MushroomManager {
+    fn default() -> Self {
+        MushroomManager {
+            mushroom_template: Mushroom::default(),
+            spawn_count: BASE_MUSHROOMS_PER_CLICK,
+            formation: Formation::default(),
+            pool: EntityPool::new(MUSHROOM_POOL_CAPACITY),
+            queue: VecDeque::new(),
+            population_cap: BASE_POPULATION_CAP,
+        }
+    }
+}
+
+impl MushroomManager {
+    //The lord doesn't count towards the cap, queued or not
+    fn queued(&self) -> i32 {
+        self.queue
+            .iter()
+            .filter(|order| order.lord_tier.is_none())
+            .map(|order| order.count)
+            .sum()
+    }
+
+    fn has_queue_space(&self) -> bool {
+        self.queue.len() < SPAWN_QUEUE_LENGTH
+    }
+
+    //How many more mushrooms fit under the cap, queued ones already have their place
+    fn room(&self, alive: i32) -> i32 {
+        (self.population_cap - alive - self.queued()).max(0)
+    }
+}
+
+#[derive(Component)]
+struct SummonManager {
+    is_summoned: bool,
+    cooldown: f32,
+    total_spores_sacrificed: i32,
+    sacrifice_amount: i32,
+}
+
+impl Default for SummonManager {
+    fn default() -> Self {
+        SummonManager {
+            is_summoned: false,
+            cooldown: 0.0,
+            total_spores_sacrificed: 0,
+            sacrifice_amount: SUMMON_MINIMUM_SPORE_COUNT,
+        }
+    }
+}
+
+impl SummonManager {
+    fn tier(&self) -> usize {
+        MUSHROOM_LORD_TIERS
+            .iter()
+            .rposition(|tier| self.total_spores_sacrificed >= tier.required_spores_sacrificed)
+            .unwrap_or(0)
+    }
+
+    fn can_summon(&self) -> bool {
+        !self.is_summoned && self.cooldown <= 0.0
+    }
+
+    //The chosen amount, limited to what the player can actually pay
+    fn sacrifice(&self, spore_count: i32) -> i32 {
+        self.sacrifice_amount
+            .min(spore_count)
+            .max(SUMMON_MINIMUM_SPORE_COUNT)
+    }
+}
+
+#[derive(Component)]
+struct Ground;
+
+#[derive(Component)]
+struct Battlefield {
+    //y of every lane, top to bottom
+    lanes: Vec<f32>,
+}
+
+impl Battlefield {
+    fn new(top: f32) -> Self {
+        Battlefield {
+            lanes: (0..LANE_COUNT)
+                .map(|lane| top - lane as f32 * LANE_SPACING)
+                .collect(),
+        }
+    }
+
+    fn nearest_lane(&self, y: f32) -> usize {
+        (0..self.lanes.len())
+            .min_by(|&a, &b| {
+                (self.lanes[a] - y)
+                    .abs()
+                    .total_cmp(&(self.lanes[b] - y).abs())
+            })
+            .unwrap_or(0)
+    }
+}
+
+//Where a unit stands, scaled up sprites are raised so their feet stay on the lane
+fn battlefield_position(transform: &Transform) -> Vec2 {
+    Vec2::new(
+        transform.translation.x,
+        transform.translation.y - TILE_SIZE / 2.0 * (transform.scale.y - 1.0),
+    )
+}
+
+//Corners of the stretch of lane running distance from position towards the base
+fn lane_towards_base(position: Vec2, distance: f32) -> (Vec2, Vec2) {
+    (
+        Vec2::new(position.x - distance, position.y - LANE_SPACING / 2.0),
+        Vec2::new(position.x, position.y + LANE_SPACING / 2.0),
+    )
+}
+
+//Where every live unit stood at the start of the fixed tick, for range queries
+#[derive(Resource)]
+struct SpatialIndex {
+    mushrooms: SpatialGrid<Entity>,
+    heroes: SpatialGrid<Entity>,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        SpatialIndex {
+            mushrooms: SpatialGrid::new(SPATIAL_INDEX_CELL_SIZE),
+            heroes: SpatialGrid::new(SPATIAL_INDEX_CELL_SIZE),
+        }
+    }
+}
+
+//Up or down towards target's lane, None when position is already in it
+fn lane_change(battlefield: &Battlefield, position: Vec2, target: Vec2) -> Option<f32> {
+    let target_lane = battlefield.nearest_lane(target.y);
+    if target_lane == battlefield.nearest_lane(position.y) {
+        return None;
+    }
+    Some((battlefield.lanes[target_lane] - position.y).signum())
+}
+
+//Keeps a y inside the lane it is already in
+fn clamp_to_lane(battlefield: &Battlefield, y: f32) -> f32 {
+    let lane_y = battlefield.lanes[battlefield.nearest_lane(y)];
+    let half_width = LANE_SPACING / 2.0 - LANE_EDGE_MARGIN;
+    y.clamp(lane_y - half_width, lane_y + half_width)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+enum Formation {
+    //Ranks as wide as the lane, filled front to back
+    #[default]
+    Line,
+    //Point first, every rank one wider than the last
+    Wedge,
+    //Scattered in a circle
+    Blob,
+}
+
+impl Formation {
+    fn next(&self) -> Formation {
+        match self {
+            Formation::Line => Formation::Wedge,
+            Formation::Wedge => Formation::Blob,
+            Formation::Blob => Formation::Line,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Formation::Line => "Line",
+            Formation::Wedge => "Wedge",
+            Formation::Blob => "Blob",
+        }
+    }
+
+    //Spawn offsets for a group, the rearmost mushroom sits at x = 0 and the group faces +x
+    fn offsets(&self, count: usize, rng: &mut impl Rng) -> Vec<Vec2> {
+        let mut offsets: Vec<Vec2> = match self {
+            Formation::Line => {
+                let rank_size =
+                    ((LANE_SPACING - 2.0 * LANE_EDGE_MARGIN) / FORMATION_SPACING) as usize + 1;
+                (0..count)
+                    .map(|i| {
+                        let rank = (i / rank_size) as f32;
+                        let file = (i % rank_size) as f32 - (rank_size - 1) as f32 / 2.0;
+                        Vec2::new(-rank, file) * FORMATION_SPACING
+                    })
+                    .collect()
+            }
+            Formation::Wedge => {
+                let mut offsets = Vec::with_capacity(count);
+                let mut rank = 0;
+                while offsets.len() < count {
+                    for file in 0..=rank {
+                        if offsets.len() == count {
+                            break;
+                        }
+                        let y = file as f32 - rank as f32 / 2.0;
+                        offsets.push(Vec2::new(-(rank as f32), y) * FORMATION_SPACING);
+                    }
+                    rank += 1;
+                }
+                offsets
+            }
+            Formation::Blob => {
+                let radius = FORMATION_SPACING * (count as f32).sqrt() / 2.0;
+                (0..count)
+                    .map(|_| {
+                        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                        let distance = radius * rng.gen::<f32>().sqrt();
+                        Vec2::from_angle(angle) * distance
+                    })
+                    .collect()
+            }
+        };
+
+        let rear = offsets.iter().map(|offset| offset.x).fold(0.0, f32::min);
+        for offset in offsets.iter_mut() {
+            offset.x -= rear;
+        }
+        offsets
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CrowdAgent {
+    position: Vec2,
+    //Not walking forward this tick, either busy or queued behind someone who is
+    stopped: bool,
+    //False for the lord, it is too big to jostle and nobody steers around it
+    in_crowd: bool,
+}
+
+//Separation push (length up to 1) and whether a stopped mushroom is blocking the way
+//Neighbours come from the spatial index, their queue state from agents as it changes
+fn crowd_steering(
+    agents: &HashMap<Entity, CrowdAgent>,
+    grid: &SpatialGrid<Entity>,
+    entity: Entity,
+) -> (Vec2, bool) {
+    let agent = agents[&entity];
+    let mut push = Vec2::ZERO;
+    let mut blocked = false;
+    let reach = Vec2::new(QUEUE_DISTANCE, QUEUE_WIDTH)
+        .length()
+        .max(SEPARATION_RADIUS);
+
+    for (_, other_entity) in grid.query_radius(agent.position, reach) {
+        if other_entity == entity {
+            continue;
+        }
+        let Some(&other) = agents.get(&other_entity).filter(|other| other.in_crowd) else {
+            continue;
+        };
+        let offset = agent.position - other.position;
+        let distance = offset.length();
+
+        if distance < SEPARATION_RADIUS {
+            //Mushrooms spawned on the exact same spot still need a way out
+            let direction = if distance > 0.0 {
+                offset / distance
+            } else {
+                Vec2::from_angle(entity.index() as f32)
+            };
+            push += direction * (1.0 - distance / SEPARATION_RADIUS);
+        }
+
+        let ahead = -offset;
+        if other.stopped
+            && ahead.x > 0.0
+            && ahead.x <= QUEUE_DISTANCE
+            && ahead.y.abs() <= QUEUE_WIDTH
+        {
+            blocked = true;
+        }
+    }
+
+    (push.clamp_length_max(1.0), blocked)
+}
+
+#[derive(Component)]
+struct Spores {
+    count: i32,
+    //Fraction of the bank lost per second, and the part of a spore lost so far
+    decay_rate: f32,
+    decayed: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+enum HeroClass {
+    Knight,
+    Archer,
+    Mage,
+    Cleric,
+}
+
+const HERO_CLASS_LIST: [HeroClass; 4] = [
+    HeroClass::Knight,
+    HeroClass::Archer,
+    HeroClass::Mage,
+    HeroClass::Cleric,
+];
+
+#[derive(Clone, Deserialize)]
+enum Growth {
+    Constant,
+    Linear(f32),
+    Exponential(f32),
+    //Multiplier of the base value per level, the last entry holds for every level after
+    Table(Vec<f32>),
+}
+
+impl Growth {
+    fn apply(&self, base: f32, level: i32) -> f32 {
+        let levels_gained = (level - HERO_BASE_LEVEL).max(0);
+        match self {
+            Growth::Constant => base,
+            Growth::Linear(rate) => base * (1.0 + rate * levels_gained as f32),
+            Growth::Exponential(rate) => base * rate.powi(levels_gained),
+            Growth::Table(multipliers) => {
+                let index = (levels_gained as usize).min(multipliers.len().saturating_sub(1));
+                base * multipliers.get(index).copied().unwrap_or(1.0)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct HeroStats {
+    hp: f32,
+    atk: f32,
+    move_speed: f32,
+    atk_speed: f32,
+    atk_range: f32,
+    armor: f32,
+    toxic_resistance: f32,
+    crit_chance: f32,
+    crit_multiplier: f32,
+    exp_required: f32,
+}
+
+#[derive(Deserialize)]
+struct HeroGrowth {
+    hp: Growth,
+    atk: Growth,
+    move_speed: Growth,
+    atk_speed: Growth,
+    atk_range: Growth,
+    armor: Growth,
+    exp_required: Growth,
+}
+
+#[derive(Deserialize)]
+struct HeroClassDefinition {
+    sheet: AnimationSheet,
+    attack_sound: String,
+    tint: (f32, f32, f32),
+    stats: HeroStats,
+    growth: HeroGrowth,
+    targeting: TargetingPolicy,
+    damage_type: DamageType,
+    on_hit_effect: Option<StatusEffect>,
+    abilities: Vec<HeroAbility>,
+}
+
+impl HeroClassDefinition {
+    fn stats_at(&self, level: i32) -> HeroStats {
+        HeroStats {
+            hp: self.growth.hp.apply(self.stats.hp, level),
+            atk: self.growth.atk.apply(self.stats.atk, level),
+            move_speed: self.growth.move_speed.apply(self.stats.move_speed, level),
+            atk_speed: self.growth.atk_speed.apply(self.stats.atk_speed, level),
+            atk_range: self.growth.atk_range.apply(self.stats.atk_range, level),
+            armor: self.growth.armor.apply(self.stats.armor, level),
+            exp_required: self
+                .growth
+                .exp_required
+                .apply(self.stats.exp_required, level),
+            ..self.stats
+        }
+    }
+
+    fn tint(&self) -> Color {
+        let (r, g, b) = self.tint;
+        Color::rgb(r, g, b)
+    }
+
+    fn hero(&self, class: HeroClass, level: i32, modifiers: &RunModifiers) -> Hero {
+        let stats = modifiers.hero_stats(self.stats_at(level));
+        Hero {
+            hp: stats.hp,
+            max_hp: stats.hp,
+            atk: stats.atk,
+            move_speed: stats.move_speed,
+            atk_speed: stats.atk_speed,
+            atk_range: stats.atk_range,
+            level,
+            exp: 0.0,
+            next_level_exp: stats.exp_required,
+            targeting: self.targeting,
+            on_hit_effect: self.on_hit_effect,
+            damage_type: self.damage_type,
+            armor: stats.armor,
+            toxic_resistance: stats.toxic_resistance,
+            crit_chance: stats.crit_chance,
+            crit_multiplier: stats.crit_multiplier,
+            phase: 0,
+            class,
+        }
+    }
+}
+
+#[derive(Resource, Deserialize)]
+struct HeroClassManager {
+    classes: HashMap<HeroClass, HeroClassDefinition>,
+}
+
+impl Index<HeroClass> for HeroClassManager {
+    type Output = HeroClassDefinition;
+    fn index(&self, class: HeroClass) -> &HeroClassDefinition {
+        &self.classes[&class]
+    }
+}
+
+#[derive(Clone, Deserialize)]
+struct WaveGroup {
+    class: HeroClass,
+    count: u32,
+    interval: f32,
+}
+
+#[derive(Clone, Deserialize)]
+struct Wave {
+    delay: f32,
+    groups: Vec<WaveGroup>,
+}
+
+#[derive(Deserialize)]
+struct WaveScript {
+    waves: Vec<Wave>,
+}
+
+#[derive(Component)]
+struct EncounterManager {
+    mode: GameMode,
+    waves: Vec<Wave>,
+    waves_cleared: usize,
+    //Index of the next wave to start
+    next_wave: usize,
+    wave_timer: f32,
+    wave_in_progress: bool,
+    //Every wave has been spawned and killed
+    finished: bool,
+    //Heroes still to spawn this wave and the time left until they do
+    spawn_queue: Vec<(HeroClass, f32)>,
+    spawn_position: Vec3,
+    //Classes for made up waves once the script runs out, empty when the run ends with the script
+    endless_classes: Vec<HeroClass>,
+    //Waves and hero lanes draw from their own stream, combat rolls depend on what the player does
+    //and would shift every wave after them
+    rng: StdRng,
+}
+
+impl EncounterManager {
+    fn new(
+        mode: GameMode,
+        script: WaveScript,
+        spawn_position: Vec3,
+        endless_classes: Vec<HeroClass>,
+        seed: u64,
+    ) -> Self {
+        let wave_timer = script.waves.first().map_or(0.0, |wave| wave.delay);
+        EncounterManager {
+            mode,
+            waves: script.waves,
+            waves_cleared: 0,
+            next_wave: 0,
+            wave_timer,
+            wave_in_progress: false,
+            finished: false,
+            spawn_queue: Vec::new(),
+            spawn_position,
+            endless_classes,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn upcoming_wave(&mut self) -> Option<&Wave> {
+        if self.next_wave >= self.waves.len() && !self.endless_classes.is_empty() {
+            let wave = endless_wave(self.waves_cleared, &self.endless_classes, &mut self.rng);
+            self.waves.push(wave);
+        }
+        self.waves.get(self.next_wave)
+    }
+
+    //Queues up the heroes of the next wave, false once there are no waves left
+    fn start_wave(&mut self) -> bool {
+        let Some(wave) = self.upcoming_wave() else {
+            return false;
+        };
+        let heroes: Vec<(HeroClass, f32)> = wave
+            .groups
+            .iter()
+            .flat_map(|group| (0..group.count).map(|i| (group.class, i as f32 * group.interval)))
+            .collect();
+        self.spawn_queue.extend(heroes);
+        self.next_wave += 1;
+        self.wave_in_progress = true;
+        true
+    }
+}
+
+#[derive(Component)]
+struct Hero {
+    hp: f32,
+    max_hp: f32,
+    atk: f32,
+    move_speed: f32,
+    atk_speed: f32,
+    atk_range: f32,
+    level: i32,
+    exp: f32,
+    next_level_exp: f32,
+    targeting: TargetingPolicy,
+    on_hit_effect: Option<StatusEffect>,
+    damage_type: DamageType,
+    armor: f32,
+    toxic_resistance: f32,
+    crit_chance: f32,
+    crit_multiplier: f32,
+    phase: usize,
+    class: HeroClass,
+}
+
+impl Hero {
+    fn attack(&self, amount: f32) -> Attack {
+        Attack {
+            amount,
+            damage_type: self.damage_type,
+            crit_chance: self.crit_chance,
+            crit_multiplier: self.crit_multiplier,
+        }
+    }
+
+    fn defense(&self) -> Defense {
+        Defense {
+            armor: self.armor,
+            toxic_resistance: self.toxic_resistance,
+        }
+    }
+
+    fn current_phase(&self) -> &'static HeroPhase {
+        &HERO_PHASES[self.phase]
+    }
+
+    //Leveling up doesn't heal, the hero keeps the same share of its new max hp
+    fn level_up(&mut self, stats: &HeroStats) {
+        if self.max_hp > 0.0 {
+            self.hp = self.hp / self.max_hp * stats.hp;
+        }
+        self.max_hp = stats.hp;
+        self.atk = stats.atk;
+        self.move_speed = stats.move_speed;
+        self.atk_speed = stats.atk_speed;
+        self.atk_range = stats.atk_range;
+        self.armor = stats.armor;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+enum TargetingPolicy {
+    Nearest,
+    LowestHp,
+    HighestThreat,
+    Cleave { max_targets: usize },
+    Splash { radius: f32 },
+}
+
+impl TargetingPolicy {
+    fn next(&self) -> TargetingPolicy {
+        match self {
+            TargetingPolicy::Nearest => TargetingPolicy::LowestHp,
+            TargetingPolicy::LowestHp => TargetingPolicy::HighestThreat,
+            TargetingPolicy::HighestThreat => TargetingPolicy::Cleave {
+                max_targets: HERO_CLEAVE_MAX_TARGETS,
+            },
+            TargetingPolicy::Cleave { .. } => TargetingPolicy::Splash {
+                radius: HERO_SPLASH_RADIUS,
+            },
+            TargetingPolicy::Splash { .. } => TargetingPolicy::Nearest,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            TargetingPolicy::Nearest => "Nearest".to_string(),
+            TargetingPolicy::LowestHp => "Lowest HP".to_string(),
+            TargetingPolicy::HighestThreat => "Highest Threat".to_string(),
+            TargetingPolicy::Cleave { max_targets } => format!("Cleave ({max_targets})"),
+            TargetingPolicy::Splash { radius } => format!("Splash ({radius})"),
+        }
+    }
+}
+
+//A mushroom the hero could hit this frame
+struct TargetCandidate {
+    entity: Entity,
+    position: Vec2,
+    hp: f32,
+    threat: f32,
+}
+
+//Current targets of a hero, with the damage multiplier applied to each
+#[derive(Component, Default)]
+struct HeroTargets {
+    targets: Vec<(Entity, f32)>,
+}
+
+#[derive(Component)]
+struct TargetMarker {
+    hero: Entity,
+    index: usize,
+}
+
+#[derive(Component)]
+struct SplashIndicator {
+    hero: Entity,
+}
+
+//The hero whose targeting policy the cycle key changes
+#[derive(Component)]
+struct SelectedHero;
+
+#[derive(Component)]
+struct SelectionRing;
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Debug, Deserialize)]
+enum HeroAbility {
+    Charge,
+    Whirlwind,
+    Heal,
+    Enrage,
+}
+
+impl HeroAbility {
+    fn cooldown(&self) -> f32 {
+        match self {
+            HeroAbility::Charge => CHARGE_COOLDOWN,
+            HeroAbility::Whirlwind => WHIRLWIND_COOLDOWN,
+            HeroAbility::Heal => HEAL_COOLDOWN,
+            HeroAbility::Enrage => f32::INFINITY,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct HeroAbilities {
+    cooldowns: HashMap<HeroAbility, f32>,
+    //Ability being telegraphed and the time left before it goes off
+    casting: Option<(HeroAbility, f32)>,
+    charge_remaining: f32,
+    elapsed: f32,
+}
+
+impl HeroAbilities {
+    fn is_ready(&self, ability: HeroAbility) -> bool {
+        self.cooldowns.get(&ability).copied().unwrap_or(0.0) <= 0.0
+    }
+
+    fn is_busy(&self) -> bool {
+        self.casting.is_some() || self.charge_remaining > 0.0
+    }
+}
+
+#[derive(Event)]
+struct HeroLevelUpEvent {
+    hero: Entity,
+    level: i32,
+}
+
+#[derive(Component)]
+struct HeroTelegraph {
+    hero: Entity,
+}
+
+struct HeroPhase {
+    hp_threshold: f32,
+    atk_multiplier: f32,
+    atk_speed_multiplier: f32,
+    move_speed_multiplier: f32,
+    scale: f32,
+    tint: Color,
+}
+
+#[derive(Component)]
+struct SporeText;
+
+#[derive(Component)]
+struct FormationText;
+
+#[derive(Component)]
+struct PopulationText;
+
+#[derive(Component)]
+struct SpawnQueueSlot {
+    index: usize,
+}
+
+#[derive(Component)]
+struct SpawnQueueFill;
+
+#[derive(Component)]
+struct SpawnQueueText;
+
+#[derive(Component)]
+struct WaveText;
+
+#[derive(Component)]
+struct HeroHPText;
+
+#[derive(Component)]
+struct HeroEXPText;
+
+#[derive(Component)]
+struct HeroLevelText;
+
+#[derive(Component)]
+struct HeroTargetingText;
+
+#[derive(Component)]
+struct HeroPhaseText;
+
+#[derive(Component)]
+struct MushroomLordHPText;
+
+#[derive(Component)]
+struct MushroomLordUI;
+
+#[derive(Component)]
+struct MushroomLordTierText;
+
+#[derive(Component)]
+struct SummonStatusText;
+
+#[derive(Component)]
+struct SummonSacrificeText;
+
+#[derive(Component)]
+struct SummonPreviewUI;
+
+#[derive(Component)]
+struct SummonPreviewText;
+
+#[derive(Clone, Copy)]
+enum SacrificeAdjustment {
+    Decrease,
+    Increase,
+    Max,
+}
+
+#[derive(Component)]
+struct SacrificeButton {
+    adjustment: SacrificeAdjustment,
+}
+
+#[derive(Component)]
+struct LordAbilityText {
+    ability: LordAbility,
+}
+
+#[derive(Component)]
+struct LordAutoCastText;
+
+fn load_assets_system(
+    mut image_manager: ResMut<ImageManager>,
+    mut animation_manager: ResMut<AnimationManager>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    asset_server: Res<AssetServer>,
+) {
+    let mushroom_base_sprite_asset: Handle<Image> = asset_server.load("./base.png");
+    let ground_sprite_asset: Handle<Image> = asset_server.load("./ground.png");
+
+    image_manager.images.insert(
+        ImageType::MushroomBase,
+        SpriteImage {
+            image_handle: mushroom_base_sprite_asset,
+        },
+    );
+
+    image_manager.images.insert(
+        ImageType::Ground,
+        SpriteImage {
+            image_handle: ground_sprite_asset,
+        },
+    );
+
+    let atlases: HashMap<AnimationSheet, Handle<TextureAtlas>> = animation_manager
+        .sheets
+        .iter()
+        .map(|(&sheet, definition)| {
+            let atlas = TextureAtlas::from_grid(
+                asset_server.load(&definition.texture),
+                Vec2::new(definition.tile_size.0, definition.tile_size.1),
+                definition.columns,
+                definition.rows,
+                None,
+                None,
+            );
+            (sheet, texture_atlases.add(atlas))
+        })
+        .collect();
+    animation_manager.atlases = atlases;
+
+    image_manager.images.insert(
+        ImageType::Background,
+        SpriteImage {
+            image_handle: asset_server.load("./background.png"),
+        },
+    );
+
+    image_manager.images.insert(
+        ImageType::SummonCircle,
+        SpriteImage {
+            image_handle: asset_server.load("./summon_circle.png"),
+        },
+    );
+}
+
+#[allow(clippy::needless_return, clippy::redundant_field_names)]
+fn setup_ui_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    run_settings: Res<RunSettings>,
+    campaign: Res<Campaign>,
+) {
+    let campaign_level = run_settings.campaign_level(&campaign);
+    let modifiers = run_settings.modifiers();
+    //Same template setup_system gives the mushroom manager, so labels start out right
+    let mushroom_template = modifiers.mushroom(Mushroom::default());
+    let font_handle = asset_server.load("./fonts/Roboto-Regular.ttf");
+    let summoning_circle_image = asset_server.load("./summon_circle.png");
+
+    //Game Over
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::BLACK),
+                z_index: ZIndex::Global(999),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            GameOverUI,
+            RunEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "GAME OVER",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 40.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                GameOverText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 24.0,
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center)
+                .with_style(Style {
+                    margin: UiRect::vertical(Val::Px(20.0)),
+                    ..default()
+                }),
+                HighScoreText,
+            ));
+        })
+        .with_children(|parent| {
+            spawn_menu_button(parent, "Menu", font_handle.clone(), BackToMenuButton);
+        });
+
+    //Spores
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(20.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Start,
+                    justify_content: JustifyContent::Center,
+                    left: Val::Percent(20.0),
+                    top: Val::Percent(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            RunEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Spores:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 40.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                SporeText,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "Formation: Line [F]",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                FormationText,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    format!("Population: 0/{BASE_POPULATION_CAP}"),
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                PopulationText,
+            ));
+            //Spawn queue, the front batch's fill grows as it builds
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        margin: UiRect::top(Val::Px(4.0)),
+                        column_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for index in 0..SPAWN_QUEUE_LENGTH {
+                        parent
+                            .spawn((
+                                NodeBundle {
+                                    style: Style {
+                                        width: Val::Px(SPAWN_QUEUE_SLOT_SIZE),
+                                        height: Val::Px(SPAWN_QUEUE_SLOT_SIZE),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    background_color: SPAWN_QUEUE_SLOT_COLOR.into(),
+                                    visibility: Visibility::Hidden,
+                                    ..default()
+                                },
+                                SpawnQueueSlot { index },
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            position_type: PositionType::Absolute,
+                                            left: Val::Px(0.0),
+                                            bottom: Val::Px(0.0),
+                                            width: Val::Percent(100.0),
+                                            height: Val::Percent(0.0),
+                                            ..default()
+                                        },
+                                        background_color: SPAWN_QUEUE_FILL_COLOR.into(),
+                                        ..default()
+                                    },
+                                    SpawnQueueFill,
+                                ));
+                                parent.spawn((
+                                    TextBundle::from_section(
+                                        "",
+                                        TextStyle {
+                                            font: font_handle.clone(),
+                                            font_size: 18.0,
+                                            color: Color::WHITE,
+                                        },
+                                    ),
+                                    SpawnQueueText,
+                                ));
+                            });
+                    }
+                });
+        });
+
+    //Mushroom Lord
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(30.0),
+                    height: Val::Percent(30.0),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Start,
+                    justify_content: JustifyContent::Start,
+                    top: Val::Percent(10.0),
+                    left: Val::Percent(20.0),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            MushroomLordUI,
+            RunEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Mushroom Lord",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                MushroomLordTierText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Mushroom Lord HP:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                MushroomLordHPText,
+            ));
+
+            for ability in LORD_ABILITIES {
+                parent.spawn((
+                    TextBundle::from_section(
+                        ability.name(),
+                        TextStyle {
+                            font: font_handle.clone(),
+                            font_size: 16.0,
+                            color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                        },
+                    ),
+                    LordAbilityText { ability },
+                ));
+            }
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "Auto-cast:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 16.0,
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                LordAutoCastText,
+            ));
+        });
+
+    //Hero stuff
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(30.0),
+                    height: Val::Percent(20.0),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Start,
+                    justify_content: JustifyContent::Start,
+                    top: Val::Percent(5.0),
+                    left: Val::Percent(80.0),
+                    ..default()
+                },
+                ..default()
+            },
+            RunEntity,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Wave:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                WaveText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Goal:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                ObjectiveText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Hero HP:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                HeroHPText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Hero EXP:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                HeroEXPText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Hero Level:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                HeroLevelText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Hero Targeting:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                HeroTargetingText,
+            ));
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Hero Phase:",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 20.0,
+                        // Alpha channel of the color controls transparency.
+                        color: Color::rgba(1.0, 1.0, 1.0, 1.0),
+                    },
+                ),
+                HeroPhaseText,
+            ));
+        });
+
+    let upgrade_button_text_style = TextStyle {
+        font: asset_server.load("./fonts/Roboto-Regular.ttf"),
+        font_size: 24.0,
+        color: Color::BLACK,
+    };
+
+    let get_cost_button_bundle = |cost| {
+        (
+            TextBundle::from_section(format!("Cost: {cost}"), upgrade_button_text_style.clone()),
+            UpgradeButtonText {
+                text_type: UpgradeTextType::Cost,
+            },
+        )
+    };
+
+    let upgrade_button_font_type = asset_server.load("./fonts/Roboto-Regular.ttf");
+    let create_upgrade_button =
+        |width, height, upgrade_type: UpgradeType, cost, cost_multiplier, text, font_type| {
+            let cost = modifiers.upgrade_cost(cost);
+            //Levels can leave some upgrades out
+            let available =
+                campaign_level.is_none_or(|level| level.upgrades.contains(&upgrade_type));
+            return move |parent: &mut ChildBuilder| {
+                if !available {
+                    return;
+                }
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(width),
+                                height: Val::Px(height),
+                                border: UiRect::all(Val::Px(2.0)),
+                                flex_direction: FlexDirection::Column,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            border_color: BorderColor(Color::BLACK),
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        },
+                        UpgradeButton {
+                            upgrade_type: upgrade_type,
+                            cost: cost,
+                            cost_multiplier: cost_multiplier,
+                            level: 0,
+                            max_level: modifiers.max_upgrade_level,
+                        },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                text,
+                                TextStyle {
+                                    font: font_type,
+                                    font_size: 24.0,
+                                    color: Color::BLACK,
+                                },
+                            ),
+                            UpgradeButtonText {
+                                text_type: UpgradeTextType::Value,
+                            },
+                        ));
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(get_cost_button_bundle(cost));
+                    });
+            };
+        };
+
+    //Upgrades
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    padding: UiRect {
+                        left: Val::Px(20.0),
+                        right: Val::Px(20.0),
+                        ..Default::default()
+                    },
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::SpaceBetween,
+                    left: Val::Percent(0.0),
+                    top: Val::Percent(20.0),
+                    ..default()
+                },
+                ..default()
+            },
+            RunEntity,
+        ))
         //Spores
         .with_children(create_upgrade_button(
             180.0,
@@ -592,785 +3319,4886 @@ fn setup_ui_system(mut commands: Commands, asset_server: Res<AssetServer>) {
             format!("Spore Count: {MUSHROOM_BASE_SPORE_COUNT}"),
             upgrade_button_font_type.clone(),
         ))
-        //Mushrooms per click
-        .with_children(create_upgrade_button(
-            240.0,
-            75.0,
-            UpgradeType::MushroomsPerClick,
-            UPGRADE_MUSHROOMS_PER_CLICK_BASE_COST,
-            UPGRADE_COST_MUSHROOMS_PER_CLICK_MULTIPLIER,
-            format!("Mushrooms per click: {BASE_MUSHROOMS_PER_CLICK}"),
-            upgrade_button_font_type.clone(),
+        //Mushrooms per click
+        .with_children(create_upgrade_button(
+            240.0,
+            75.0,
+            UpgradeType::MushroomsPerClick,
+            UPGRADE_MUSHROOMS_PER_CLICK_BASE_COST,
+            UPGRADE_COST_MUSHROOMS_PER_CLICK_MULTIPLIER,
+            format!("Mushrooms per click: {BASE_MUSHROOMS_PER_CLICK}"),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            160.0,
+            75.0,
+            UpgradeType::HP,
+            UPGRADE_MUSHROOM_HP_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("HP: {}", mushroom_template.hp),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            160.0,
+            75.0,
+            UpgradeType::ATK,
+            UPGRADE_MUSHROOM_ATK_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("ATK: {}", mushroom_template.atk),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            160.0,
+            75.0,
+            UpgradeType::AtkSpeed,
+            UPGRADE_MUSHROOM_ATK_SPEED_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("ATK Speed: {}", mushroom_template.atk_speed),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            180.0,
+            75.0,
+            UpgradeType::MoveSpeed,
+            UPGRADE_MUSHROOM_MOVE_SPEED_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("Move Speed: {}", mushroom_template.move_speed),
+            upgrade_button_font_type.clone(),
+        ));
+
+    //More upgrades
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    padding: UiRect {
+                        left: Val::Px(20.0),
+                        right: Val::Px(20.0),
+                        ..Default::default()
+                    },
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::SpaceBetween,
+                    left: Val::Percent(0.0),
+                    top: Val::Percent(32.0),
+                    ..default()
+                },
+                ..default()
+            },
+            RunEntity,
+        ))
+        .with_children(create_upgrade_button(
+            180.0,
+            75.0,
+            UpgradeType::CritChance,
+            UPGRADE_MUSHROOM_CRIT_CHANCE_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("Crit Chance: {}", mushroom_template.crit_chance),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            180.0,
+            75.0,
+            UpgradeType::CritMultiplier,
+            UPGRADE_MUSHROOM_CRIT_MULTIPLIER_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("Crit Damage: {}", mushroom_template.crit_multiplier),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            180.0,
+            75.0,
+            UpgradeType::BaseWalls,
+            UPGRADE_BASE_WALLS_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("Base HP: {BASE_STRUCTURE_HP}"),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            180.0,
+            75.0,
+            UpgradeType::BaseRegen,
+            UPGRADE_BASE_REGEN_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("Base Regen: {BASE_STRUCTURE_REGEN}"),
+            upgrade_button_font_type.clone(),
+        ))
+        .with_children(create_upgrade_button(
+            180.0,
+            75.0,
+            UpgradeType::PopulationCap,
+            UPGRADE_POPULATION_CAP_BASE_COST,
+            UPGRADE_COST_BASE_MULTIPLIER,
+            format!("Population: {BASE_POPULATION_CAP}"),
+            upgrade_button_font_type.clone(),
+        ));
+
+    let create_sacrifice_button = |adjustment, text: &str| {
+        let text = text.to_string();
+        let font = font_handle.clone();
+        move |parent: &mut ChildBuilder| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(48.0),
+                            height: Val::Px(48.0),
+                            border: UiRect::all(Val::Px(2.0)),
+                            margin: UiRect::horizontal(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        border_color: BorderColor(Color::BLACK),
+                        background_color: NORMAL_BUTTON.into(),
+                        ..default()
+                    },
+                    SacrificeButton { adjustment },
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        text,
+                        TextStyle {
+                            font,
+                            font_size: 20.0,
+                            color: Color::BLACK,
+                        },
+                    ));
+                });
+        }
+    };
+
+    //Summon commands
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    top: Val::Percent(37.5),
+                    ..default()
+                },
+                ..default()
+            },
+            RunEntity,
+        ))
+        .with_children(create_sacrifice_button(SacrificeAdjustment::Decrease, "-"))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(128.0),
+                            height: Val::Px(128.0),
+                            flex_direction: FlexDirection::Column,
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        image: UiImage {
+                            texture: summoning_circle_image,
+                            ..default()
+                        },
+                        background_color: SUMMON_BUTTON_INACTIVE_COLOR,
+                        ..default()
+                    },
+                    SummonButton,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Tier 1",
+                            TextStyle {
+                                font: font_handle.clone(),
+                                font_size: 16.0,
+                                color: Color::BLACK,
+                            },
+                        ),
+                        SummonStatusText,
+                    ));
+                    parent.spawn((
+                        TextBundle::from_section(
+                            format!("{SUMMON_MINIMUM_SPORE_COUNT} spores"),
+                            TextStyle {
+                                font: font_handle.clone(),
+                                font_size: 16.0,
+                                color: Color::BLACK,
+                            },
+                        ),
+                        SummonSacrificeText,
+                    ));
+
+                    //Projected stats, shown while hovering
+                    parent
+                        .spawn((
+                            NodeBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    width: Val::Px(SUMMON_PREVIEW_WIDTH),
+                                    bottom: Val::Px(136.0),
+                                    padding: UiRect::all(Val::Px(8.0)),
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.8)),
+                                visibility: Visibility::Hidden,
+                                ..default()
+                            },
+                            SummonPreviewUI,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        font: font_handle.clone(),
+                                        font_size: 16.0,
+                                        color: Color::WHITE,
+                                    },
+                                ),
+                                SummonPreviewText,
+                            ));
+                        });
+                });
+        })
+        .with_children(create_sacrifice_button(SacrificeAdjustment::Increase, "+"))
+        .with_children(create_sacrifice_button(SacrificeAdjustment::Max, "Max"));
+}
+
+fn setup_camera_system(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle::default(),
+        GameCamera,
+        ScreenShake::default(),
+    ));
+}
+
+fn setup_system(
+    mut commands: Commands,
+    image_manager: Res<ImageManager>,
+    run_settings: Res<RunSettings>,
+    campaign: Res<Campaign>,
+    asset_server: Res<AssetServer>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let mushroom_base_sprite = &image_manager[ImageType::MushroomBase];
+    let campaign_level = run_settings.campaign_level(&campaign);
+    let (ground_texture, background_texture) = match campaign_level {
+        Some(level) => (
+            asset_server.load(&level.ground),
+            asset_server.load(&level.background),
+        ),
+        None => (
+            image_manager[ImageType::Ground].handle(),
+            image_manager[ImageType::Background].handle(),
+        ),
+    };
+
+    let window = q_windows.single();
+    let width = window.width();
+    let height = window.height();
+
+    let tile_x_count = (width / TILE_SIZE) as i32;
+    let tile_y_count = ((height / TILE_SIZE) / 2.0) as i32;
+
+    let tile_size = TILE_SIZE as i32;
+    let x_offset = width / 2.0;
+    let y_offset = height / 2.0;
+
+    //Ground
+    for i in 0..tile_x_count + 1 {
+        for j in 0..tile_y_count {
+            let x = (i * tile_size) as f32 - x_offset;
+            let y = (j * tile_size) as f32 - y_offset;
+
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform {
+                        translation: Vec3::new(x, y, 0.0),
+                        scale: (Vec3::splat(1.0)),
+                        ..default()
+                    },
+                    texture: ground_texture.clone(),
+                    ..default()
+                },
+                Ground,
+                RunEntity,
+            ));
+        }
+    }
+
+    //Background
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, -1.0),
+                scale: (Vec3::splat(1.0)),
+                ..default()
+            },
+            texture: background_texture,
+            ..default()
+        },
+        RunEntity,
+    ));
+
+    let initial_height = -y_offset + (tile_y_count as f32) * TILE_SIZE;
+    let battlefield = Battlefield::new(initial_height);
+    for &lane_y in battlefield.lanes.iter() {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: LANE_MARKER_COLOR,
+                    custom_size: Some(Vec2::new(width, LANE_MARKER_HEIGHT)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, lane_y - TILE_SIZE / 2.0, 0.5),
+                ..default()
+            },
+            RunEntity,
+        ));
+    }
+    commands.spawn((battlefield, RunEntity));
+    let wave_script: WaveScript = match (campaign_level, &run_settings.daily) {
+        (Some(level), _) => WaveScript {
+            waves: level.waves.clone(),
+        },
+        (_, Some(_)) => WaveScript { waves: Vec::new() },
+        _ => ron::from_str(WAVE_SCRIPT).expect("invalid wave script"),
+    };
+    let endless_classes = match (run_settings.mode, &run_settings.daily) {
+        (GameMode::Daily, Some(daily)) => vec![daily.class],
+        (GameMode::Endless, _) => HERO_CLASS_LIST.to_vec(),
+        _ => Vec::new(),
+    };
+    commands.spawn((
+        EncounterManager::new(
+            run_settings.mode,
+            wave_script,
+            Vec3::new(x_offset - TILE_SIZE, initial_height, 0.0),
+            endless_classes,
+            run_settings.seed,
+        ),
+        RunEntity,
+    ));
+    commands.insert_resource(GameRng(StdRng::seed_from_u64(run_settings.seed)));
+
+    let modifiers = run_settings.modifiers();
+    commands.spawn((
+        Spores {
+            count: match (campaign_level, &run_settings.daily) {
+                (Some(level), _) => level.starting_spores,
+                (_, Some(daily)) => daily.starting_spores,
+                _ => INITIAL_SPORE_COUNT,
+            },
+            decay_rate: modifiers.spore_decay,
+            decayed: 0.0,
+        },
+        RunEntity,
+    ));
+
+    commands
+        .spawn((
+            SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(-x_offset + TILE_SIZE, initial_height, 0.0),
+                    scale: (Vec3::splat(GLOBAL_SCALE)),
+                    ..default()
+                },
+                texture: mushroom_base_sprite.handle(),
+                ..default()
+            },
+            MushroomBase::default(),
+            RunEntity,
+        ))
+        .with_children(|parent| {
+            spawn_health_bar(
+                parent,
+                Vec2::new(BASE_HEALTH_BAR_WIDTH, BASE_HEALTH_BAR_HEIGHT),
+                BASE_HEALTH_BAR_OFFSET_Y,
+                true,
+            );
+        });
+
+    commands.spawn((
+        MushroomManager {
+            mushroom_template: modifiers.mushroom(Mushroom::default()),
+            ..default()
+        },
+        RunEntity,
+    ));
+
+    commands.spawn((SummonManager { ..default() }, RunEntity));
+    let win_conditions = match (run_settings.mode, campaign_level) {
+        (GameMode::Campaign, Some(level)) => level.win_conditions.clone(),
+        (GameMode::Endless | GameMode::Daily, _) => Vec::new(),
+        _ => vec![WinCondition::ClearAllWaves],
+    };
+    commands.spawn((
+        GameManager {
+            win_conditions,
+            ..default()
+        },
+        RunEntity,
+    ));
+}
+
+#[allow(clippy::type_complexity)]
+fn upgrade_button_system(
+    mut q_interaction: Query<
+        (&Interaction, &mut UpgradeButton, &Children),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+    mut q_button_text: Query<(&mut Text, &UpgradeButtonText)>,
+    mut q_spores: Query<&mut Spores>,
+    q_hero: Query<&Hero>,
+    mut q_mushroom_base: Query<&mut MushroomBase>,
+    run_settings: Res<RunSettings>,
+) {
+    let modifiers = run_settings.modifiers();
+    let mut manager = q_mushroom_manager.single_mut();
+    let mut spores = q_spores.single_mut();
+    let hero_level = q_hero
+        .iter()
+        .map(|hero| hero.level)
+        .max()
+        .unwrap_or(HERO_BASE_LEVEL);
+    let mut mushroom_base = q_mushroom_base.single_mut();
+
+    for (interaction, mut button, children) in &mut q_interaction {
+        match *interaction {
+            Interaction::Pressed => {
+                let maxed = button
+                    .max_level
+                    .is_some_and(|max_level| button.level >= max_level);
+                if maxed || spores.count < button.cost {
+                    continue;
+                }
+
+                spores.count -= button.cost;
+                button.cost *= button.cost_multiplier;
+                button.level += 1;
+                let cost = button.cost;
+                let maxed = button
+                    .max_level
+                    .is_some_and(|max_level| button.level >= max_level);
+
+                let mut update_button_text = |child, text: &String| {
+                    match q_button_text.get_mut(child) {
+                        Ok((mut button_text, upgrade_button_text)) => {
+                            match upgrade_button_text.text_type {
+                                UpgradeTextType::Value => {
+                                    button_text.sections[0].value = text.clone();
+                                }
+                                UpgradeTextType::Cost if maxed => {
+                                    button_text.sections[0].value = "MAX".to_string()
+                                }
+                                UpgradeTextType::Cost => {
+                                    button_text.sections[0].value = format!("Cost: {cost}")
+                                }
+                            }
+                        }
+                        Err(_) => todo!(),
+                    };
+                };
+
+                let mut update_button_children = |text: String| {
+                    for &child in children.iter() {
+                        update_button_text(child, &text);
+                    }
+                };
+
+                match button.upgrade_type {
+                    UpgradeType::SporeCount => {
+                        manager.mushroom_template.spore_count += modifiers.spores(hero_level);
+                        let spore_count = manager.mushroom_template.spore_count;
+
+                        update_button_children(format!("Spore count: {spore_count}"));
+                    }
+                    UpgradeType::MushroomsPerClick => {
+                        manager.spawn_count += 1;
+                        let spawn_count = manager.spawn_count;
+
+                        update_button_children(format!("Mushrooms per click: {spawn_count}"));
+                    }
+                    UpgradeType::HP => {
+                        manager.mushroom_template.hp += MUSHROOM_BASE_HP * modifiers.mushroom_hp;
+                        manager.mushroom_template.max_hp = manager.mushroom_template.hp;
+                        let hp = manager.mushroom_template.hp;
+
+                        update_button_children(format!("HP: {hp}"));
+                    }
+                    UpgradeType::ATK => {
+                        manager.mushroom_template.atk += MUSHROOM_BASE_ATK * modifiers.mushroom_atk;
+                        let atk = manager.mushroom_template.atk;
+
+                        update_button_children(format!("ATK: {atk}"));
+                    }
+                    UpgradeType::AtkSpeed => {
+                        manager.mushroom_template.atk_speed += MUSHROOM_BASE_ATK_SPEED;
+                        let atk_speed = manager.mushroom_template.atk_speed;
+
+                        update_button_children(format!("ATK Speed: {atk_speed}"));
+                    }
+                    UpgradeType::MoveSpeed => {
+                        manager.mushroom_template.move_speed += MUSHROOM_BASE_MOVE_SPEED;
+                        let move_speed = manager.mushroom_template.move_speed;
+
+                        update_button_children(format!("Move Speed: {move_speed}"));
+                    }
+                    UpgradeType::CritChance => {
+                        manager.mushroom_template.crit_chance += UPGRADE_CRIT_CHANCE_AMOUNT;
+                        let crit_chance = manager.mushroom_template.crit_chance;
+
+                        update_button_children(format!("Crit Chance: {crit_chance}"));
+                    }
+                    UpgradeType::CritMultiplier => {
+                        manager.mushroom_template.crit_multiplier += UPGRADE_CRIT_MULTIPLIER_AMOUNT;
+                        let crit_multiplier = manager.mushroom_template.crit_multiplier;
+
+                        update_button_children(format!("Crit Damage: {crit_multiplier}"));
+                    }
+                    UpgradeType::BaseWalls => {
+                        mushroom_base.max_hp += UPGRADE_BASE_WALLS_HP_AMOUNT;
+                        mushroom_base.hp += UPGRADE_BASE_WALLS_HP_AMOUNT;
+                        mushroom_base.armor += UPGRADE_BASE_WALLS_ARMOR_AMOUNT;
+                        let max_hp = mushroom_base.max_hp;
+
+                        update_button_children(format!("Base HP: {max_hp}"));
+                    }
+                    UpgradeType::BaseRegen => {
+                        mushroom_base.regen += UPGRADE_BASE_REGEN_AMOUNT;
+                        let regen = mushroom_base.regen;
+
+                        update_button_children(format!("Base Regen: {regen}"));
+                    }
+                    UpgradeType::PopulationCap => {
+                        manager.population_cap += UPGRADE_POPULATION_CAP_AMOUNT;
+                        let population_cap = manager.population_cap;
+
+                        update_button_children(format!("Population: {population_cap}"));
+                    }
+                }
+            }
+            Interaction::Hovered => {}
+            Interaction::None => {}
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn button_system(
+    mut interaction_query: Query<
+        (
+            &Interaction,
+            &mut BackgroundColor,
+            &mut BorderColor,
+            &Children,
+        ),
+        (Changed<Interaction>, With<Button>, Without<SummonButton>),
+    >,
+) {
+    for (interaction, mut color, mut border_color, _children) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+                border_color.0 = Color::RED;
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+                border_color.0 = Color::WHITE;
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+fn game_over_system(
+    mut q_game_manager: Query<&mut GameManager>,
+    mut q_game_over_ui: Query<&mut Visibility, With<GameOverUI>>,
+    mut q_game_over_text: Query<&mut Text, With<GameOverText>>,
+    mut q_high_score_text: Query<&mut Text, (With<HighScoreText>, Without<GameOverText>)>,
+    q_encounter_manager: Query<&EncounterManager>,
+    run_settings: Res<RunSettings>,
+) {
+    let mut game_manager = q_game_manager.single_mut();
+    let mut visibility = q_game_over_ui.single_mut();
+    let mut game_over_text = q_game_over_text.single_mut();
+
+    if game_manager.game_over {
+        *visibility = Visibility::Visible;
+
+        if game_manager.victory {
+            game_over_text.sections[0].value = "VICTORY".to_string();
+        } else {
+            game_over_text.sections[0].value = "GAME OVER".to_string();
+        }
+
+        if game_manager.score_recorded {
+            return;
+        }
+        game_manager.score_recorded = true;
+
+        if game_manager.victory && run_settings.mode == GameMode::Campaign {
+            let mut progress = CampaignProgress::load();
+            progress.record(run_settings.level, &run_settings);
+            progress.save();
+        }
+
+        let encounter_manager = q_encounter_manager.single();
+        let score = HighScore {
+            mode: encounter_manager.mode,
+            difficulty: run_settings.difficulty,
+            mutators: run_settings.mutators.clone(),
+            waves_survived: encounter_manager.waves_cleared,
+            peak_spores: game_manager.peak_spores,
+            time: game_manager.run_time,
+        };
+        let summary = format!(
+            "{}\nWaves: {}   Peak spores: {}   Time: {:.0}s\n\n",
+            run_settings.description(),
+            score.waves_survived,
+            score.peak_spores,
+            score.time
+        );
+
+        if let Some(daily) = &run_settings.daily {
+            let mut leaderboard = DailyLeaderboard::load();
+            leaderboard.insert(DailyScore {
+                date: daily.date.clone(),
+                seed: run_settings.seed,
+                score,
+            });
+            leaderboard.save();
+
+            let mut text = summary
+                + &format!(
+                    "Daily {} leaderboard (seed {})\n",
+                    daily.date, run_settings.seed
+                );
+            for (i, daily_score) in leaderboard.top(&daily.date).enumerate() {
+                text += &format!(
+                    "{}. Waves: {}   Peak spores: {}   Time: {:.0}s\n",
+                    i + 1,
+                    daily_score.score.waves_survived,
+                    daily_score.score.peak_spores,
+                    daily_score.score.time
+                );
+            }
+            q_high_score_text.single_mut().sections[0].value = text;
+            return;
+        }
+
+        let mut high_score_table = HighScoreTable::load();
+        high_score_table.insert(score.clone());
+        high_score_table.save();
+
+        let mut text = summary + &format!("{} high scores\n", score.mode.name());
+        for (i, high_score) in high_score_table.top(score.mode).enumerate() {
+            text += &format!(
+                "{}. Waves: {}   Peak spores: {}   Time: {:.0}s   {:?} +{} mutators\n",
+                i + 1,
+                high_score.waves_survived,
+                high_score.peak_spores,
+                high_score.time,
+                high_score.difficulty,
+                high_score.mutators.len()
+            );
+        }
+        q_high_score_text.single_mut().sections[0].value = text;
+    }
+}
+
+fn win_condition_system(
+    mut q_game_manager: Query<&mut GameManager>,
+    q_encounter_manager: Query<&EncounterManager>,
+    q_spores: Query<&Spores>,
+) {
+    let mut game_manager = q_game_manager.single_mut();
+    if game_manager.game_over {
+        return;
+    }
+    let encounter_manager = q_encounter_manager.single();
+    let spores = q_spores.single();
+
+    let won = game_manager
+        .win_conditions
+        .iter()
+        .any(|condition| condition.is_met(&game_manager, encounter_manager, spores));
+    if won {
+        game_manager.game_over = true;
+        game_manager.victory = true;
+    }
+}
+
+fn spore_decay_system(mut q_spores: Query<&mut Spores>, time: Res<Time>) {
+    let mut spores = q_spores.single_mut();
+    if spores.decay_rate <= 0.0 {
+        return;
+    }
+
+    spores.decayed += spores.count as f32 * spores.decay_rate * time.delta_seconds();
+    let lost = spores.decayed.floor();
+    spores.count -= lost as i32;
+    spores.decayed -= lost;
+}
+
+fn run_stats_system(
+    mut q_game_manager: Query<&mut GameManager>,
+    q_spores: Query<&Spores>,
+    time: Res<Time>,
+) {
+    let mut game_manager = q_game_manager.single_mut();
+    if game_manager.game_over {
+        return;
+    }
+
+    game_manager.run_time += time.delta_seconds();
+    game_manager.peak_spores = game_manager.peak_spores.max(q_spores.single().count);
+}
+
+fn spawn_menu_button(
+    parent: &mut ChildBuilder,
+    text: &str,
+    font: Handle<Font>,
+    button: impl Component,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(200.0),
+                    height: Val::Px(60.0),
+                    margin: UiRect::all(Val::Px(10.0)),
+                    border: UiRect::all(Val::Px(2.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                border_color: BorderColor(Color::BLACK),
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font,
+                    font_size: 24.0,
+                    color: Color::BLACK,
+                },
+            ));
+        });
+}
+
+fn setup_menu_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+) {
+    let font_handle = asset_server.load("./fonts/Roboto-Regular.ttf");
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::BLACK),
+                ..default()
+            },
+            MenuUI,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Choose a mode",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                }),
+            );
+
+            for mode in [
+                GameMode::Campaign,
+                GameMode::Waves,
+                GameMode::Endless,
+                GameMode::Daily,
+            ] {
+                spawn_menu_button(
+                    parent,
+                    mode.name(),
+                    font_handle.clone(),
+                    ModeButton { mode },
+                );
+            }
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        margin: UiRect::top(Val::Px(20.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for setting in [
+                        Setting::DamageNumbers,
+                        Setting::HitFlash,
+                        Setting::ScreenShake,
+                        Setting::FullHealthBars,
+                    ] {
+                        spawn_menu_button(
+                            parent,
+                            &setting.label(&settings),
+                            font_handle.clone(),
+                            SettingButton { setting },
+                        );
+                    }
+                });
+        });
+}
+
+fn setting_button_system(
+    q_interaction: Query<(&Interaction, &SettingButton, &Children), Changed<Interaction>>,
+    mut q_text: Query<&mut Text>,
+    mut settings: ResMut<Settings>,
+) {
+    for (interaction, setting_button, children) in &q_interaction {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        let value = setting_button.setting.value_mut(&mut settings);
+        *value = !*value;
+        settings.save();
+
+        for &child in children.iter() {
+            if let Ok(mut text) = q_text.get_mut(child) {
+                text.sections[0].value = setting_button.setting.label(&settings);
+            }
+        }
+    }
+}
+
+fn cleanup_menu_system(mut commands: Commands, q_menu_ui: Query<Entity, With<MenuUI>>) {
+    for entity in &q_menu_ui {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn mode_button_system(
+    q_interaction: Query<(&Interaction, &ModeButton), Changed<Interaction>>,
+    mut run_settings: ResMut<RunSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, mode_button) in &q_interaction {
+        if *interaction == Interaction::Pressed {
+            run_settings.mode = mode_button.mode;
+            next_state.set(match mode_button.mode {
+                GameMode::Campaign => AppState::LevelSelect,
+                //The daily challenge decides everything itself
+                GameMode::Daily => {
+                    *run_settings = RunSettings::daily(&today());
+                    AppState::InGame
+                }
+                _ => AppState::RunSetup,
+            });
+        }
+    }
+}
+
+fn setup_level_select_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    campaign: Res<Campaign>,
+) {
+    let font_handle = asset_server.load("./fonts/Roboto-Regular.ttf");
+    let progress = CampaignProgress::load();
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::BLACK),
+                ..default()
+            },
+            LevelSelectUI,
         ))
-        .with_children(create_upgrade_button(
-            160.0,
-            75.0,
-            UpgradeType::HP,
-            UPGRADE_MUSHROOM_HP_BASE_COST,
-            UPGRADE_COST_BASE_MULTIPLIER,
-            format!("HP: {MUSHROOM_BASE_HP}"),
-            upgrade_button_font_type.clone(),
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Choose a level",
+                    TextStyle {
+                        font: font_handle.clone(),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                }),
+            );
+
+            for (i, level) in campaign.levels.iter().enumerate() {
+                let unlocked = progress.is_unlocked(i);
+                let text = if let Some(difficulty) = progress.best_difficulty(i) {
+                    format!("{}. {} ({difficulty:?})", i + 1, level.name)
+                } else if unlocked {
+                    format!("{}. {}", i + 1, level.name)
+                } else {
+                    format!("{}. Locked", i + 1)
+                };
+                spawn_menu_button(
+                    parent,
+                    &text,
+                    font_handle.clone(),
+                    LevelButton { level: i, unlocked },
+                );
+            }
+
+            spawn_menu_button(parent, "Back", font_handle.clone(), BackToMenuButton);
+        });
+}
+
+fn cleanup_level_select_system(
+    mut commands: Commands,
+    q_level_select_ui: Query<Entity, With<LevelSelectUI>>,
+) {
+    for entity in &q_level_select_ui {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn level_button_system(
+    mut q_level_button: Query<(&Interaction, &LevelButton, &mut BackgroundColor)>,
+    mut run_settings: ResMut<RunSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, level_button, mut color) in &mut q_level_button {
+        if !level_button.unlocked {
+            *color = LOCKED_LEVEL_COLOR.into();
+            continue;
+        }
+
+        if *interaction == Interaction::Pressed {
+            run_settings.mode = GameMode::Campaign;
+            run_settings.level = level_button.level;
+            next_state.set(AppState::RunSetup);
+        }
+    }
+}
+
+fn setup_run_setup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font_handle = asset_server.load("./fonts/Roboto-Regular.ttf");
+    let heading = |text: &str| {
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font: font_handle.clone(),
+                font_size: 32.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::vertical(Val::Px(10.0)),
+            ..default()
+        })
+    };
+    let row = || NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            ..default()
+        },
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::BLACK),
+                ..default()
+            },
+            RunSetupUI,
         ))
-        .with_children(create_upgrade_button(
-            160.0,
-            75.0,
-            UpgradeType::ATK,
-            UPGRADE_MUSHROOM_ATK_BASE_COST,
-            UPGRADE_COST_BASE_MULTIPLIER,
-            format!("ATK: {MUSHROOM_BASE_ATK}"),
-            upgrade_button_font_type.clone(),
+        .with_children(|parent| {
+            parent.spawn(heading("Difficulty"));
+            parent.spawn(row()).with_children(|parent| {
+                for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+                    spawn_menu_button(
+                        parent,
+                        &format!("{difficulty:?}"),
+                        font_handle.clone(),
+                        DifficultyButton { difficulty },
+                    );
+                }
+            });
+
+            parent.spawn(heading("Mutators"));
+            parent.spawn(row()).with_children(|parent| {
+                for mutator in MUTATORS {
+                    spawn_menu_button(
+                        parent,
+                        mutator.name(),
+                        font_handle.clone(),
+                        MutatorButton { mutator },
+                    );
+                }
+            });
+
+            parent.spawn(row()).with_children(|parent| {
+                spawn_menu_button(parent, "Back", font_handle.clone(), BackToMenuButton);
+                spawn_menu_button(parent, "Start", font_handle.clone(), StartRunButton);
+            });
+        });
+}
+
+fn cleanup_run_setup_system(
+    mut commands: Commands,
+    q_run_setup_ui: Query<Entity, With<RunSetupUI>>,
+) {
+    for entity in &q_run_setup_ui {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn run_setup_button_system(
+    mut q_difficulty_button: Query<
+        (Ref<Interaction>, &DifficultyButton, &mut BackgroundColor),
+        Without<MutatorButton>,
+    >,
+    mut q_mutator_button: Query<
+        (Ref<Interaction>, &MutatorButton, &mut BackgroundColor),
+        Without<DifficultyButton>,
+    >,
+    q_start_button: Query<&Interaction, (Changed<Interaction>, With<StartRunButton>)>,
+    mut run_settings: ResMut<RunSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let pressed = |interaction: &Ref<Interaction>| {
+        interaction.is_changed() && **interaction == Interaction::Pressed
+    };
+    //Hovering and pressing are drawn by button_system, otherwise show what's selected
+    let idle_color = |selected: bool| {
+        if selected {
+            SELECTED_BUTTON.into()
+        } else {
+            NORMAL_BUTTON.into()
+        }
+    };
+
+    for (interaction, difficulty_button, _) in &q_difficulty_button {
+        if pressed(&interaction) {
+            run_settings.difficulty = difficulty_button.difficulty;
+        }
+    }
+    for (interaction, difficulty_button, mut color) in &mut q_difficulty_button {
+        if *interaction == Interaction::None {
+            *color = idle_color(run_settings.difficulty == difficulty_button.difficulty);
+        }
+    }
+
+    for (interaction, mutator_button, mut color) in &mut q_mutator_button {
+        let mutator = mutator_button.mutator;
+        if pressed(&interaction) {
+            match run_settings.mutators.iter().position(|m| *m == mutator) {
+                Some(index) => {
+                    run_settings.mutators.remove(index);
+                }
+                None => run_settings.mutators.push(mutator),
+            }
+        }
+        if *interaction == Interaction::None {
+            *color = idle_color(run_settings.mutators.contains(&mutator));
+        }
+    }
+
+    for interaction in &q_start_button {
+        if *interaction == Interaction::Pressed {
+            run_settings.seed = rand::random();
+            run_settings.daily = None;
+            next_state.set(AppState::InGame);
+        }
+    }
+}
+
+fn back_to_menu_button_system(
+    q_interaction: Query<&Interaction, (Changed<Interaction>, With<BackToMenuButton>)>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for interaction in &q_interaction {
+        if *interaction == Interaction::Pressed {
+            next_state.set(AppState::Menu);
+        }
+    }
+}
+
+fn cleanup_run_system(mut commands: Commands, q_run_entities: Query<Entity, With<RunEntity>>) {
+    for entity in &q_run_entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn summon_button_system(
+    mut q_summon_button_interaction: Query<
+        (&Interaction, &mut BackgroundColor),
+        With<SummonButton>,
+    >,
+    mut q_summon_manager: Query<&mut SummonManager>,
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+    mut q_spores: Query<&mut Spores>,
+    run_settings: Res<RunSettings>,
+) {
+    let mut summon_manager = q_summon_manager.single_mut();
+    let mut mushroom_manager = q_mushroom_manager.single_mut();
+    let mut spores = q_spores.single_mut();
+
+    for (interaction, mut background_color) in &mut q_summon_button_interaction {
+        if spores.count < SUMMON_MINIMUM_SPORE_COUNT
+            || !summon_manager.can_summon()
+            || !mushroom_manager.has_queue_space()
+        {
+            *background_color = SUMMON_BUTTON_INACTIVE_COLOR;
+            return;
+        } else {
+            *background_color = SUMMON_BUTTON_ACTIVE_COLOR;
+        }
+
+        match *interaction {
+            Interaction::Pressed => {
+                let tier_index = summon_manager.tier();
+                let tier = &MUSHROOM_LORD_TIERS[tier_index];
+                let sacrifice = summon_manager.sacrifice(spores.count);
+                let mushroom_lord_stats = run_settings
+                    .modifiers()
+                    .mushroom(mushroom_lord_stats(tier, sacrifice));
+
+                mushroom_manager
+                    .queue
+                    .push_back(SpawnOrder::lord(tier_index, mushroom_lord_stats));
+                summon_manager.total_spores_sacrificed += sacrifice;
+                spores.count -= sacrifice;
+                summon_manager.is_summoned = true;
+            }
+            Interaction::Hovered => {}
+            Interaction::None => {}
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn mushroom_lord_ability_system(
+    time: Res<Time>,
+    mut q_mushroom_lord: Query<
+        (&Mushroom, &Transform, &mut LordAbilities, &StatusEffects),
+        (With<MushroomLord>, Without<DeathSequence>),
+    >,
+    mut q_hero: Query<
+        (Entity, &mut Hero, &Transform, &mut StatusEffects),
+        (Without<Mushroom>, Without<DeathSequence>),
+    >,
+    mut q_mushroom: Query<
+        &mut StatusEffects,
+        (
+            With<Mushroom>,
+            Without<MushroomLord>,
+            Without<DeathSequence>,
+        ),
+    >,
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+    q_battlefield: Query<&Battlefield>,
+    spatial_index: Res<SpatialIndex>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let Ok((lord, lord_transform, mut abilities, lord_status_effects)) =
+        q_mushroom_lord.get_single_mut()
+    else {
+        return;
+    };
+    let mut mushroom_manager = q_mushroom_manager.single_mut();
+    let mushroom_manager = &mut *mushroom_manager;
+    let requested = std::mem::take(&mut abilities.requested);
+
+    for cooldown in abilities.cooldowns.values_mut() {
+        *cooldown = (*cooldown - time.delta_seconds()).max(0.0);
+    }
+
+    if lord_status_effects.is_stunned() {
+        return;
+    }
+
+    let lord_position = battlefield_position(lord_transform);
+    let nearest_hero = spatial_index.heroes.nearest(lord_position, f32::INFINITY);
+    let distance_to_hero = nearest_hero.map_or(f32::INFINITY, |(hero_position, _)| {
+        hero_position.distance(lord_position)
+    });
+    let mut rng = &mut game_rng.0;
+
+    for ability in LORD_ABILITIES {
+        if abilities.cooldown(ability) > 0.0 {
+            continue;
+        }
+
+        let wants_to_cast = match ability {
+            LordAbility::SporeNova => distance_to_hero <= SPORE_NOVA_RADIUS,
+            LordAbility::Rally => distance_to_hero <= RALLY_RADIUS,
+            LordAbility::Root => distance_to_hero <= ROOT_RANGE,
+            LordAbility::SpawnMinions => true,
+        };
+        let cast = requested.contains(&ability) || (abilities.auto_cast && wants_to_cast);
+        if !cast {
+            continue;
+        }
+
+        //A cast that hits nothing doesn't go on cooldown
+        let landed = match ability {
+            LordAbility::SporeNova => {
+                let nova = Attack {
+                    damage_type: DamageType::Toxic,
+                    ..lord.attack(lord.atk * SPORE_NOVA_ATK_MULTIPLIER)
+                };
+                let mut hit = false;
+                for (_, hero_entity) in spatial_index
+                    .heroes
+                    .query_radius(lord_position, SPORE_NOVA_RADIUS)
+                {
+                    let Ok((_, mut hero, hero_transform, mut hero_status_effects)) =
+                        q_hero.get_mut(hero_entity)
+                    else {
+                        continue;
+                    };
+                    let damage = resolve_damage(&nova, &hero.defense(), &mut rng);
+                    hero.hp -= damage.amount;
+                    hero_status_effects.apply(SPORE_NOVA_EFFECT);
+                    damage_events.send(DamageEvent::new(
+                        hero_entity,
+                        hero_transform.translation,
+                        &nova,
+                        &damage,
+                    ));
+                    hit = true;
+                }
+                hit
+            }
+            LordAbility::Rally => {
+                let mut rallied = false;
+                for (_, mushroom_entity) in spatial_index
+                    .mushrooms
+                    .query_radius(lord_position, RALLY_RADIUS)
+                {
+                    if let Ok(mut status_effects) = q_mushroom.get_mut(mushroom_entity) {
+                        for effect in RALLY_EFFECTS {
+                            status_effects.apply(effect);
+                        }
+                        rallied = true;
+                    }
+                }
+                rallied
+            }
+            LordAbility::Root => {
+                let target = nearest_hero
+                    .filter(|_| distance_to_hero <= ROOT_RANGE)
+                    .and_then(|(_, hero_entity)| q_hero.get_mut(hero_entity).ok());
+                match target {
+                    Some((_, _, _, mut hero_status_effects)) => {
+                        hero_status_effects.apply(ROOT_EFFECT);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            LordAbility::SpawnMinions => {
+                //Minions are queued for the lord's lane like any other batch, just quicker
+                let alive = q_mushroom.iter().count() as i32;
+                let minion_count = SPAWN_MINIONS_COUNT.min(mushroom_manager.room(alive));
+                let queued = minion_count > 0 && mushroom_manager.has_queue_space();
+                if queued {
+                    let order = SpawnOrder::new(
+                        q_battlefield.single().nearest_lane(lord_position.y),
+                        minion_count,
+                        mushroom_manager.formation,
+                        Mushroom {
+                            build_time: SPAWN_MINIONS_BUILD_TIME,
+                            ..mushroom_manager.mushroom_template
+                        },
+                    );
+                    mushroom_manager.queue.push_back(order);
+                }
+                queued
+            }
+        };
+
+        if landed {
+            abilities.cooldowns.insert(ability, ability.cooldown());
+        }
+    }
+}
+
+//Keys are read every frame, the abilities go off on the next fixed tick
+fn mushroom_lord_ability_input_system(
+    mut q_mushroom_lord: Query<&mut LordAbilities, (With<MushroomLord>, Without<DeathSequence>)>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    let Ok(mut abilities) = q_mushroom_lord.get_single_mut() else {
+        return;
+    };
+
+    if keyboard.just_pressed(MUSHROOM_LORD_AUTO_CAST_KEY) {
+        abilities.auto_cast = !abilities.auto_cast;
+    }
+    for ability in LORD_ABILITIES {
+        if keyboard.just_pressed(ability.key()) && !abilities.requested.contains(&ability) {
+            abilities.requested.push(ability);
+        }
+    }
+}
+
+fn mushroom_lord_ability_text_update_system(
+    mut q_lord_ability_text: Query<(&mut Text, &LordAbilityText)>,
+    mut q_lord_auto_cast_text: Query<&mut Text, (With<LordAutoCastText>, Without<LordAbilityText>)>,
+    q_mushroom_lord: Query<&LordAbilities, With<MushroomLord>>,
+) {
+    let Ok(abilities) = q_mushroom_lord.get_single() else {
+        return;
+    };
+
+    for (mut text, lord_ability_text) in &mut q_lord_ability_text {
+        let ability = lord_ability_text.ability;
+        let name = ability.name();
+        let key = format!("{:?}", ability.key());
+        let cooldown = abilities.cooldown(ability);
+
+        text.sections[0].value = if cooldown > 0.0 {
+            let cooldown = cooldown.ceil();
+            format!("[{key}] {name}: {cooldown}s")
+        } else {
+            format!("[{key}] {name}: Ready")
+        };
+    }
+
+    let auto_cast = if abilities.auto_cast { "On" } else { "Off" };
+    let auto_cast_key = format!("{:?}", MUSHROOM_LORD_AUTO_CAST_KEY);
+    q_lord_auto_cast_text.single_mut().sections[0].value =
+        format!("[{auto_cast_key}] Auto-cast: {auto_cast}");
+}
+
+fn sacrifice_button_system(
+    q_interaction: Query<(&Interaction, &SacrificeButton), Changed<Interaction>>,
+    mut q_summon_manager: Query<&mut SummonManager>,
+    q_spores: Query<&Spores>,
+) {
+    let mut summon_manager = q_summon_manager.single_mut();
+    let spore_count = q_spores.single().count;
+
+    for (interaction, sacrifice_button) in &q_interaction {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        let current = summon_manager.sacrifice(spore_count);
+        summon_manager.sacrifice_amount = match sacrifice_button.adjustment {
+            SacrificeAdjustment::Decrease => {
+                (current - SUMMON_SACRIFICE_STEP).max(SUMMON_MINIMUM_SPORE_COUNT)
+            }
+            SacrificeAdjustment::Increase => current + SUMMON_SACRIFICE_STEP,
+            SacrificeAdjustment::Max => i32::MAX,
+        };
+    }
+}
+
+fn summon_preview_system(
+    q_summon_button: Query<&Interaction, With<SummonButton>>,
+    q_summon_manager: Query<&SummonManager>,
+    q_spores: Query<&Spores>,
+    mut q_summon_preview_ui: Query<&mut Visibility, With<SummonPreviewUI>>,
+    mut q_summon_preview_text: Query<&mut Text, With<SummonPreviewText>>,
+    mut q_summon_sacrifice_text: Query<
+        &mut Text,
+        (With<SummonSacrificeText>, Without<SummonPreviewText>),
+    >,
+    run_settings: Res<RunSettings>,
+) {
+    let interaction = q_summon_button.single();
+    let summon_manager = q_summon_manager.single();
+    let spore_count = q_spores.single().count;
+    let mut visibility = q_summon_preview_ui.single_mut();
+
+    let sacrifice = summon_manager.sacrifice(spore_count);
+    q_summon_sacrifice_text.single_mut().sections[0].value = format!("{sacrifice} spores");
+
+    if *interaction == Interaction::None {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    let tier = &MUSHROOM_LORD_TIERS[summon_manager.tier()];
+    let stats = run_settings
+        .modifiers()
+        .mushroom(mushroom_lord_stats(tier, sacrifice));
+    let name = tier.name;
+    let hp = stats.hp;
+    let atk = stats.atk;
+    let atk_speed = stats.atk_speed;
+    let atk_range = stats.atk_range;
+
+    let mut preview = format!(
+        "{name}\nSacrifice: {sacrifice} spores\nHP: {hp}\nATK: {atk}\nATK Speed: {atk_speed}\nRange: {atk_range}"
+    );
+    if spore_count < SUMMON_MINIMUM_SPORE_COUNT {
+        preview.push_str(&format!("\nNeeds {SUMMON_MINIMUM_SPORE_COUNT} spores"));
+    }
+    q_summon_preview_text.single_mut().sections[0].value = preview;
+}
+
+fn summon_cooldown_system(mut q_summon_manager: Query<&mut SummonManager>, time: Res<Time>) {
+    let mut summon_manager = q_summon_manager.single_mut();
+    if summon_manager.cooldown > 0.0 {
+        summon_manager.cooldown -= time.delta_seconds();
+    }
+}
+
+fn summon_status_text_update_system(
+    mut q_summon_status_text: Query<&mut Text, With<SummonStatusText>>,
+    q_summon_manager: Query<&SummonManager>,
+) {
+    let mut text = q_summon_status_text.single_mut();
+    let summon_manager = q_summon_manager.single();
+
+    text.sections[0].value = if summon_manager.is_summoned {
+        "Summoned".to_string()
+    } else if summon_manager.cooldown > 0.0 {
+        let cooldown = summon_manager.cooldown.ceil();
+        format!("Cooldown: {cooldown}s")
+    } else {
+        let tier = summon_manager.tier() + 1;
+        format!("Tier {tier}")
+    };
+}
+
+fn hero_movement_system(
+    mut q_hero: Query<(&Hero, &mut Transform, &Unit, &StatusEffects, &HeroAbilities)>,
+    time: Res<Time>,
+) {
+    q_hero.for_each_mut(
+        |(hero, mut hero_transform, unit, status_effects, abilities)| {
+            if unit.state != UnitState::Advancing || abilities.is_busy() {
+                return;
+            }
+            hero_transform.translation.x -= time.delta_seconds()
+                * hero.move_speed
+                * hero.current_phase().move_speed_multiplier
+                * status_effects.move_speed_multiplier();
+        },
+    );
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn hero_ability_system(
+    mut q_hero: Query<
+        (
+            &mut Hero,
+            &mut Transform,
+            &mut HeroAbilities,
+            &mut StatusEffects,
+            &Unit,
+        ),
+        Without<DeathSequence>,
+    >,
+    mut q_mushroom: Query<
+        (&mut Mushroom, &Transform, &mut StatusEffects),
+        (Without<Hero>, Without<DeathSequence>),
+    >,
+    q_mushroom_base: Query<&Transform, (With<MushroomBase>, Without<Hero>, Without<Mushroom>)>,
+    q_game_manager: Query<&GameManager>,
+    spatial_index: Res<SpatialIndex>,
+    hero_class_manager: Res<HeroClassManager>,
+    time: Res<Time>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    if q_game_manager.single().game_over {
+        return;
+    }
+    let mushroom_base_position = q_mushroom_base.single().translation;
+    let mut rng = &mut game_rng.0;
+
+    //Heals affect every hero nearby, so look at everyone before anyone acts
+    let hero_hp_fractions: Vec<(Vec2, f32)> = q_hero
+        .iter()
+        .map(|(hero, hero_transform, _, _, _)| {
+            (battlefield_position(hero_transform), hero.hp / hero.max_hp)
+        })
+        .collect();
+    let mut heals: Vec<Vec2> = Vec::new();
+
+    q_hero.for_each_mut(
+        |(hero, mut hero_transform, mut abilities, mut status_effects, unit)| {
+            let delta = time.delta_seconds();
+            abilities.elapsed += delta;
+            for cooldown in abilities.cooldowns.values_mut() {
+                *cooldown -= delta;
+            }
+
+            //Mid-dash, keep moving towards the base
+            if abilities.charge_remaining > 0.0 {
+                let step = (CHARGE_SPEED * delta).min(abilities.charge_remaining);
+                let min_x = mushroom_base_position.x + hero.atk_range;
+                hero_transform.translation.x = (hero_transform.translation.x - step).max(min_x);
+                abilities.charge_remaining -= step;
+                if hero_transform.translation.x <= min_x {
+                    abilities.charge_remaining = 0.0;
+                }
+                return;
+            }
+
+            if status_effects.is_stunned() {
+                return;
+            }
+
+            let hero_position = battlefield_position(&hero_transform);
+            let hero_x = hero_position.x;
+            let atk =
+                hero.atk * hero.current_phase().atk_multiplier * status_effects.atk_multiplier();
+
+            if let Some((ability, telegraph_remaining)) = abilities.casting {
+                let telegraph_remaining = telegraph_remaining - delta;
+                if telegraph_remaining > 0.0 {
+                    abilities.casting = Some((ability, telegraph_remaining));
+                    return;
+                }
+                abilities.casting = None;
+                abilities.cooldowns.insert(ability, ability.cooldown());
+
+                match ability {
+                    HeroAbility::Charge => {
+                        let charge = hero.attack(atk * CHARGE_ATK_MULTIPLIER);
+                        //Everything in the hero's lane along the dash
+                        let (min, max) = lane_towards_base(hero_position, CHARGE_DISTANCE);
+                        for (_, mushroom_entity) in spatial_index.mushrooms.query_rect(min, max) {
+                            let Ok((mut mushroom, mushroom_transform, mut mushroom_effects)) =
+                                q_mushroom.get_mut(mushroom_entity)
+                            else {
+                                continue;
+                            };
+                            let damage = resolve_damage(&charge, &mushroom.defense(), &mut rng);
+                            mushroom.hp -= damage.amount;
+                            mushroom_effects.apply(CHARGE_EFFECT);
+                            damage_events.send(DamageEvent::new(
+                                mushroom_entity,
+                                mushroom_transform.translation,
+                                &charge,
+                                &damage,
+                            ));
+                        }
+                        abilities.charge_remaining = CHARGE_DISTANCE;
+                    }
+                    HeroAbility::Whirlwind => {
+                        let whirlwind = hero.attack(atk * WHIRLWIND_ATK_MULTIPLIER);
+                        let spun = spatial_index
+                            .mushrooms
+                            .query_radius(hero_position, WHIRLWIND_RADIUS);
+                        for (_, mushroom_entity) in spun {
+                            let Ok((mut mushroom, mushroom_transform, _)) =
+                                q_mushroom.get_mut(mushroom_entity)
+                            else {
+                                continue;
+                            };
+                            let damage = resolve_damage(&whirlwind, &mushroom.defense(), &mut rng);
+                            mushroom.hp -= damage.amount;
+                            damage_events.send(DamageEvent::new(
+                                mushroom_entity,
+                                mushroom_transform.translation,
+                                &whirlwind,
+                                &damage,
+                            ));
+                        }
+                    }
+                    HeroAbility::Heal => heals.push(hero_position),
+                    HeroAbility::Enrage => {
+                        for effect in ENRAGE_EFFECTS {
+                            status_effects.apply(effect);
+                        }
+                    }
+                }
+                return;
+            }
+
+            //Pick the most urgent ability that is ready
+            let nearby_mushrooms = spatial_index
+                .mushrooms
+                .query_radius(hero_position, WHIRLWIND_RADIUS)
+                .count();
+            let (min, max) = lane_towards_base(hero_position, CHARGE_TRIGGER_RANGE);
+            let charge_target_in_range = spatial_index
+                .mushrooms
+                .query_rect(min, max)
+                .any(|(mushroom_position, _)| hero_x - mushroom_position.x > hero.atk_range);
+
+            let ally_needs_healing =
+                hero_hp_fractions
+                    .iter()
+                    .any(|&(ally_position, hp_fraction)| {
+                        ally_position.distance(hero_position) <= HEAL_RADIUS
+                            && hp_fraction <= HEAL_HP_THRESHOLD
+                    });
+            let class_definition = &hero_class_manager[hero.class];
+            let can_cast = |ability| {
+                class_definition.abilities.contains(&ability) && abilities.is_ready(ability)
+            };
+
+            let ability = if can_cast(HeroAbility::Heal) && ally_needs_healing {
+                Some(HeroAbility::Heal)
+            } else if can_cast(HeroAbility::Enrage) && abilities.elapsed >= ENRAGE_TIME_LIMIT {
+                Some(HeroAbility::Enrage)
+            } else if can_cast(HeroAbility::Whirlwind) && nearby_mushrooms >= WHIRLWIND_MIN_TARGETS
+            {
+                Some(HeroAbility::Whirlwind)
+            } else if can_cast(HeroAbility::Charge) && !unit.is_engaged() && charge_target_in_range
+            {
+                Some(HeroAbility::Charge)
+            } else {
+                None
+            };
+
+            if let Some(ability) = ability {
+                abilities.casting = Some((ability, HERO_TELEGRAPH_TIME));
+            }
+        },
+    );
+
+    for healer_position in heals {
+        q_hero.for_each_mut(|(mut hero, hero_transform, _, _, _)| {
+            if battlefield_position(&hero_transform).distance(healer_position) <= HEAL_RADIUS {
+                hero.hp = (hero.hp + hero.max_hp * HEAL_AMOUNT).min(hero.max_hp);
+            }
+        });
+    }
+}
+
+fn hero_telegraph_system(
+    q_hero: Query<(&Transform, &HeroAbilities)>,
+    mut q_telegraph: Query<
+        (&HeroTelegraph, &mut Transform, &mut Sprite, &mut Visibility),
+        Without<HeroAbilities>,
+    >,
+) {
+    for (telegraph, mut telegraph_transform, mut sprite, mut visibility) in &mut q_telegraph {
+        let Ok((hero_transform, abilities)) = q_hero.get(telegraph.hero) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        let Some((ability, _)) = abilities.casting else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        let hero_position = hero_transform.translation.truncate();
+        let (position, size, color) = match ability {
+            HeroAbility::Charge => (
+                hero_position - Vec2::new(CHARGE_DISTANCE / 2.0, 0.0),
+                Vec2::new(CHARGE_DISTANCE, TILE_SIZE * CHARGE_TELEGRAPH_HEIGHT),
+                CHARGE_TELEGRAPH_COLOR,
+            ),
+            HeroAbility::Whirlwind => (
+                hero_position,
+                Vec2::splat(WHIRLWIND_RADIUS * 2.0),
+                WHIRLWIND_TELEGRAPH_COLOR,
+            ),
+            HeroAbility::Heal => (
+                hero_position,
+                Vec2::splat(HEAL_RADIUS * 2.0),
+                HEAL_TELEGRAPH_COLOR,
+            ),
+            HeroAbility::Enrage => (
+                hero_position,
+                Vec2::splat(ENRAGE_TELEGRAPH_RADIUS * 2.0),
+                ENRAGE_TELEGRAPH_COLOR,
+            ),
+        };
+
+        telegraph_transform.translation = position.extend(0.5);
+        telegraph_transform.scale = (size / TILE_SIZE).extend(1.0);
+        sprite.color = color;
+        *visibility = Visibility::Visible;
+    }
+}
+
+fn hero_phase_system(
+    mut q_hero: Query<(&mut Hero, &mut Transform, &mut SpriteTint)>,
+    hero_class_manager: Res<HeroClassManager>,
+) {
+    q_hero.for_each_mut(|(mut hero, mut hero_transform, mut sprite_tint)| {
+        let hp_fraction = hero.hp / hero.max_hp;
+
+        //Phases only move forward, healing doesn't undo them
+        let phase = HERO_PHASES
+            .iter()
+            .rposition(|phase| hp_fraction <= phase.hp_threshold)
+            .unwrap_or(0)
+            .max(hero.phase);
+
+        if phase == hero.phase {
+            return;
+        }
+
+        hero.phase = phase;
+        let current_phase = hero.current_phase();
+        hero_transform.scale = Vec3::splat(GLOBAL_SCALE * current_phase.scale);
+        let class_tint = hero_class_manager[hero.class].tint();
+        sprite_tint.color = current_phase.tint * Vec4::from(class_tint.as_rgba_f32());
+    });
+}
+
+//Adds exp and returns every level reached, overflow carries into the next level
+fn gain_hero_exp(hero: &mut Hero, amount: f32, class_definition: &HeroClassDefinition) -> Vec<i32> {
+    let mut levels_reached = Vec::new();
+    hero.exp += amount;
+
+    while hero.next_level_exp > 0.0 && hero.exp >= hero.next_level_exp {
+        hero.exp -= hero.next_level_exp;
+        hero.level += 1;
+        hero.next_level_exp = class_definition.stats_at(hero.level).exp_required;
+        levels_reached.push(hero.level);
+    }
+
+    levels_reached
+}
+
+fn hero_exp_system(
+    mut q_hero: Query<(Entity, &mut Hero)>,
+    mut level_up_events: EventWriter<HeroLevelUpEvent>,
+    hero_class_manager: Res<HeroClassManager>,
+    time: Res<Time>,
+) {
+    q_hero.for_each_mut(|(hero_entity, mut hero)| {
+        let class_definition = &hero_class_manager[hero.class];
+        let amount = time.delta_seconds() * HERO_EXP_PER_SECOND;
+
+        for level in gain_hero_exp(&mut hero, amount, class_definition) {
+            level_up_events.send(HeroLevelUpEvent {
+                hero: hero_entity,
+                level,
+            });
+        }
+    });
+}
+
+fn hero_level_up_system(
+    mut level_up_events: EventReader<HeroLevelUpEvent>,
+    mut q_hero: Query<&mut Hero>,
+    hero_class_manager: Res<HeroClassManager>,
+    run_settings: Res<RunSettings>,
+) {
+    let modifiers = run_settings.modifiers();
+    for event in level_up_events.read() {
+        let Ok(mut hero) = q_hero.get_mut(event.hero) else {
+            continue;
+        };
+
+        let stats = modifiers.hero_stats(hero_class_manager[hero.class].stats_at(event.level));
+        hero.level_up(&stats);
+    }
+}
+
+fn select_hero_targets(
+    policy: TargetingPolicy,
+    hero_position: Vec2,
+    candidates: &[TargetCandidate],
+) -> Vec<(Entity, f32)> {
+    let distance_to_hero = |candidate: &TargetCandidate| hero_position.distance(candidate.position);
+
+    let nearest = candidates
+        .iter()
+        .min_by(|a, b| distance_to_hero(a).total_cmp(&distance_to_hero(b)));
+
+    match policy {
+        TargetingPolicy::Nearest => nearest.map(|c| vec![(c.entity, 1.0)]).unwrap_or_default(),
+        TargetingPolicy::LowestHp => candidates
+            .iter()
+            .min_by(|a, b| a.hp.total_cmp(&b.hp))
+            .map(|c| vec![(c.entity, 1.0)])
+            .unwrap_or_default(),
+        TargetingPolicy::HighestThreat => candidates
+            .iter()
+            .max_by(|a, b| a.threat.total_cmp(&b.threat))
+            .map(|c| vec![(c.entity, 1.0)])
+            .unwrap_or_default(),
+        TargetingPolicy::Cleave { max_targets } => {
+            let mut sorted: Vec<&TargetCandidate> = candidates.iter().collect();
+            sorted.sort_by(|a, b| distance_to_hero(a).total_cmp(&distance_to_hero(b)));
+            sorted
+                .iter()
+                .take(max_targets)
+                .map(|c| (c.entity, 1.0))
+                .collect()
+        }
+        TargetingPolicy::Splash { radius } => {
+            let Some(primary) = nearest else {
+                return Vec::new();
+            };
+
+            let mut targets = vec![(primary.entity, 1.0)];
+            for candidate in candidates {
+                if candidate.entity == primary.entity {
+                    continue;
+                }
+                if candidate.position.distance(primary.position) <= radius {
+                    targets.push((candidate.entity, HERO_SPLASH_DAMAGE_MULTIPLIER));
+                }
+            }
+            targets
+        }
+    }
+}
+
+//Closest hero to the cursor, as long as the cursor is roughly on top of it
+fn hero_under_cursor(cursor: Vec2, heroes: impl Iterator<Item = (Entity, Vec2)>) -> Option<Entity> {
+    heroes
+        .map(|(entity, position)| (entity, position.distance(cursor)))
+        .filter(|&(_, distance)| distance <= HERO_SELECT_RADIUS)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+#[allow(clippy::type_complexity)]
+fn hero_selection_system(
+    mut commands: Commands,
+    q_hero: Query<(Entity, &Transform), (With<Hero>, Without<DeathSequence>)>,
+    q_selected: Query<Entity, With<SelectedHero>>,
+    q_selection_ring: Query<Entity, With<SelectionRing>>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    mouse: Res<Input<MouseButton>>,
+) {
+    if !mouse.just_pressed(HERO_SELECT_BUTTON) {
+        return;
+    }
+
+    let (camera, camera_transform) = q_camera.single();
+    let Some(cursor) = q_windows
+        .single()
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+    else {
+        return;
+    };
+
+    //Clicking empty ground clears the selection
+    for entity in &q_selected {
+        commands.entity(entity).remove::<SelectedHero>();
+    }
+    for entity in &q_selection_ring {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let heroes = q_hero
+        .iter()
+        .map(|(entity, transform)| (entity, transform.translation.truncate()));
+    let Some(hero_entity) = hero_under_cursor(cursor, heroes) else {
+        return;
+    };
+
+    commands
+        .entity(hero_entity)
+        .insert(SelectedHero)
+        .with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: SELECTION_RING_COLOR,
+                        custom_size: Some(Vec2::new(
+                            SELECTION_RING_SIZE,
+                            SELECTION_RING_SIZE / 2.0,
+                        )),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, -TILE_SIZE / 2.0, -0.1),
+                    ..default()
+                },
+                SelectionRing,
+            ));
+        });
+}
+
+fn hero_targeting_input_system(
+    mut q_hero: Query<&mut Hero, (With<SelectedHero>, Without<DeathSequence>)>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    if !keyboard.just_pressed(HERO_TARGETING_CYCLE_KEY) {
+        return;
+    }
+
+    for mut hero in &mut q_hero {
+        hero.targeting = hero.targeting.next();
+    }
+}
+
+//lol
+#[allow(clippy::type_complexity)]
+fn hero_attack_system(
+    mut q_hero: Query<
+        (
+            &Hero,
+            &Transform,
+            &mut Unit,
+            &mut HeroTargets,
+            &StatusEffects,
+            &HeroAbilities,
+            &mut Animation,
+        ),
+        Without<DeathSequence>,
+    >,
+    q_mushroom: Query<&Mushroom, (Without<Hero>, Without<DeathSequence>)>,
+    q_mushroom_base: Query<&Transform, (With<MushroomBase>, Without<Hero>, Without<Mushroom>)>,
+    q_game_manager: Query<&GameManager>,
+    spatial_index: Res<SpatialIndex>,
+    animation_manager: Res<AnimationManager>,
+    time: Res<Time>,
+) {
+    let mushroom_base_transform = q_mushroom_base.single();
+    let game_manager = q_game_manager.single();
+
+    q_hero.for_each_mut(|hero_data| {
+        let (
+            hero,
+            hero_transform,
+            mut unit,
+            mut hero_targets,
+            hero_status_effects,
+            hero_abilities,
+            mut animation,
+        ) = hero_data;
+
+        //The base spans every lane, only how far along the hero is matters
+        let distance_to_base =
+            (hero_transform.translation.x - mushroom_base_transform.translation.x).abs();
+        let base_in_range = distance_to_base <= hero.atk_range && !game_manager.game_over;
+
+        let hero_position = battlefield_position(hero_transform);
+        let candidates: Vec<TargetCandidate> = if game_manager.game_over {
+            Vec::new()
+        } else {
+            spatial_index
+                .mushrooms
+                .query_radius(hero_position, hero.atk_range)
+                .filter_map(|(position, entity)| {
+                    let mushroom = q_mushroom.get(entity).ok()?;
+                    Some(TargetCandidate {
+                        entity,
+                        position,
+                        hp: mushroom.hp,
+                        threat: mushroom.atk * mushroom.atk_speed,
+                    })
+                })
+                .collect()
+        };
+
+        hero_targets.targets = select_hero_targets(hero.targeting, hero_position, &candidates);
+
+        let senses = UnitSenses {
+            target_in_range: !candidates.is_empty() || base_in_range,
+            stunned: hero_status_effects.is_stunned(),
+            dead: hero.hp <= 0.0,
+            busy: hero_abilities.is_busy(),
+            attack_finished: !animation.is_playing(AnimationClip::Attack),
+        };
+
+        //The hit itself lands on the impact frame, see hero_attack_impact_system
+        if unit.update(&senses, time.delta_seconds()) == Some(UnitState::Attacking) {
+            let cooldown = 1.0
+                / (hero.atk_speed
+                    * hero.current_phase().atk_speed_multiplier
+                    * hero_status_effects.atk_speed_multiplier());
+            let clip = animation_manager.clip(animation.sheet, AnimationClip::Attack);
+            animation.restart(AnimationClip::Attack, (clip.duration() / cooldown).max(1.0));
+            unit.attack_cooldown = cooldown;
+        }
+    });
+}
+
+#[allow(
+    clippy::type_complexity,
+    clippy::too_many_arguments,
+    clippy::needless_update
+)]
+fn hero_attack_impact_system(
+    mut animation_events: EventReader<AnimationEvent>,
+    q_hero: Query<(&Hero, &Transform, &HeroTargets, &StatusEffects, &Unit)>,
+    mut q_mushroom: Query<(&mut Mushroom, &Transform, &mut StatusEffects), Without<Hero>>,
+    mut q_mushroom_base: Query<
+        (Entity, &mut MushroomBase, &Transform),
+        (Without<Hero>, Without<Mushroom>),
+    >,
+    q_game_manager: Query<&GameManager>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    hero_class_manager: Res<HeroClassManager>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let (mushroom_base_entity, mut mushroom_base, mushroom_base_transform) =
+        q_mushroom_base.single_mut();
+    let game_manager = q_game_manager.single();
+    let rng = &mut game_rng.0;
+
+    for animation_event in animation_events.read() {
+        if animation_event.event != FrameEvent::Impact || game_manager.game_over {
+            continue;
+        }
+        let Ok((hero, hero_transform, hero_targets, hero_status_effects, unit)) =
+            q_hero.get(animation_event.entity)
+        else {
+            continue;
+        };
+        if unit.state != UnitState::Attacking {
+            continue;
+        }
+
+        let atk =
+            hero.atk * hero.current_phase().atk_multiplier * hero_status_effects.atk_multiplier();
+
+        for &(target, damage_multiplier) in hero_targets.targets.iter() {
+            if let Ok((mut mushroom, mushroom_transform, mut mushroom_status_effects)) =
+                q_mushroom.get_mut(target)
+            {
+                let attack = hero.attack(atk * damage_multiplier);
+                let damage = resolve_damage(&attack, &mushroom.defense(), rng);
+                mushroom.hp -= damage.amount;
+                if let Some(on_hit_effect) = hero.on_hit_effect {
+                    mushroom_status_effects.apply(on_hit_effect);
+                }
+                damage_events.send(DamageEvent::new(
+                    target,
+                    mushroom_transform.translation,
+                    &attack,
+                    &damage,
+                ));
+            }
+        }
+
+        //Only lay siege to the base once the defenders are out of the way
+        let distance_to_base =
+            (hero_transform.translation.x - mushroom_base_transform.translation.x).abs();
+        if hero_targets.targets.is_empty() && distance_to_base <= hero.atk_range {
+            let attack = hero.attack(atk);
+            let damage = resolve_damage(&attack, &mushroom_base.defense(), rng);
+            mushroom_base.hp -= damage.amount;
+            damage_events.send(DamageEvent::new(
+                mushroom_base_entity,
+                mushroom_base_transform.translation,
+                &attack,
+                &damage,
+            ));
+        }
+
+        //hack to make it play every time
+        //lazy to make another system for this
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(&hero_class_manager[hero.class].attack_sound),
+                settings: PlaybackSettings::DESPAWN,
+                ..default()
+            },
+            HeroAttackAudio,
+            RunEntity,
+        ));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn hero_target_marker_system(
+    mut commands: Commands,
+    image_manager: Res<ImageManager>,
+    q_hero: Query<(Entity, &Hero, &HeroTargets), Without<DeathSequence>>,
+    q_target_transform: Query<&Transform, (With<Mushroom>, Without<TargetMarker>)>,
+    mut q_marker: Query<
+        (Entity, &TargetMarker, &mut Transform, &mut Visibility),
+        (Without<Mushroom>, Without<SplashIndicator>),
+    >,
+    mut q_splash_indicator: Query<
+        (&SplashIndicator, &mut Transform, &mut Visibility),
+        (Without<Mushroom>, Without<TargetMarker>),
+    >,
+) {
+    let mut marker_counts: HashMap<Entity, usize> = HashMap::new();
+
+    for (marker_entity, marker, mut marker_transform, mut visibility) in &mut q_marker {
+        //Markers past the end of the target list go, cleave and splash counts come and go
+        let Some(&(target, _)) = q_hero
+            .get(marker.hero)
+            .ok()
+            .and_then(|(_, _, hero_targets)| hero_targets.targets.get(marker.index))
+        else {
+            commands.entity(marker_entity).despawn();
+            continue;
+        };
+        *marker_counts.entry(marker.hero).or_insert(0) += 1;
+
+        let target_transform = q_target_transform.get(target).ok();
+
+        match target_transform {
+            Some(target_transform) => {
+                marker_transform.translation = target_transform.translation
+                    + Vec3::new(0.0, TARGET_MARKER_OFFSET_Y * target_transform.scale.y, 2.0);
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+
+    for (splash_indicator, mut indicator_transform, mut visibility) in &mut q_splash_indicator {
+        let Ok((_, hero, hero_targets)) = q_hero.get(splash_indicator.hero) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        let primary_transform = hero_targets
+            .targets
+            .first()
+            .and_then(|&(target, _)| q_target_transform.get(target).ok());
+
+        match (hero.targeting, primary_transform) {
+            (TargetingPolicy::Splash { radius }, Some(primary_transform)) => {
+                indicator_transform.translation =
+                    primary_transform.translation.truncate().extend(0.5);
+                indicator_transform.scale = Vec3::splat(radius * 2.0 / TILE_SIZE);
+                *visibility = Visibility::Visible;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+
+    //Spawn any markers that are missing
+    for (hero_entity, hero, hero_targets) in &q_hero {
+        let marker_count = marker_counts.get(&hero_entity).copied().unwrap_or(0);
+        for index in marker_count..hero_targets.targets.len() {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: TARGET_MARKER_COLOR,
+                        custom_size: Some(Vec2::splat(TARGET_MARKER_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_rotation(Quat::from_rotation_z(
+                        std::f32::consts::FRAC_PI_4,
+                    )),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                TargetMarker {
+                    hero: hero_entity,
+                    index,
+                },
+                RunEntity,
+            ));
+        }
+
+        let has_splash_indicator = q_splash_indicator
+            .iter()
+            .any(|(splash_indicator, _, _)| splash_indicator.hero == hero_entity);
+        if !has_splash_indicator && matches!(hero.targeting, TargetingPolicy::Splash { .. }) {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: SPLASH_INDICATOR_COLOR,
+                        ..default()
+                    },
+                    texture: image_manager[ImageType::SummonCircle].handle(),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                SplashIndicator { hero: hero_entity },
+                RunEntity,
+            ));
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn mushroom_death_system(
+    mut commands: Commands,
+    mut q_mushroom: Query<
+        (
+            Entity,
+            &Transform,
+            &Mushroom,
+            &mut Unit,
+            &mut StatusEffects,
+            Option<&MushroomLord>,
+        ),
+        (Without<Hero>, Without<DeathSequence>),
+    >,
+    q_mushroom_manager: Query<&MushroomManager>,
+    mut q_hero: Query<
+        (Entity, &mut Hero, &Transform, &mut StatusEffects),
+        (Without<Mushroom>, Without<DeathSequence>),
+    >,
+    mut q_summon_manager: Query<&mut SummonManager>,
+    mut q_mushroom_lord_ui_visibility: Query<&mut Visibility, With<MushroomLordUI>>,
+    mut level_up_events: EventWriter<HeroLevelUpEvent>,
+    hero_class_manager: Res<HeroClassManager>,
+    spatial_index: Res<SpatialIndex>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let mushroom_manager = q_mushroom_manager.single();
+    let mut summon_manager = q_summon_manager.single_mut();
+    let mut mushroom_lord_ui_visibility = q_mushroom_lord_ui_visibility.single_mut();
+
+    for (entity, transform, mushroom, mut unit, mut status_effects, lord) in &mut q_mushroom {
+        if mushroom.hp > 0.0 {
+            continue;
+        }
+
+        unit.die();
+        status_effects.active.clear();
+        commands.entity(entity).insert(DeathSequence::default());
+        spawn_spore_burst(
+            &mut commands,
+            transform.translation,
+            mushroom_manager.mushroom_template.spore_count,
+            &mut game_rng.0,
+        );
+
+        //The closest hero gets the credit
+        if let Some((_, hero_entity)) = spatial_index
+            .heroes
+            .nearest(battlefield_position(transform), f32::INFINITY)
+        {
+            if let Ok((_, mut hero, _, _)) = q_hero.get_mut(hero_entity) {
+                let amount = mushroom.xp_drop * HERO_KILL_EXP_MULTIPLIER;
+                let class_definition = &hero_class_manager[hero.class];
+                for level in gain_hero_exp(&mut hero, amount, class_definition) {
+                    level_up_events.send(HeroLevelUpEvent {
+                        hero: hero_entity,
+                        level,
+                    });
+                }
+            }
+        }
+
+        if lord.is_some() {
+            summon_manager.is_summoned = false;
+            summon_manager.cooldown = SUMMON_COOLDOWN;
+            *mushroom_lord_ui_visibility = Visibility::Hidden;
+        }
+    }
+}
+
+//Splits the spores over a handful of particles flung upwards
+fn spore_burst(total: i32, rng: &mut impl Rng) -> Vec<(i32, Vec2)> {
+    let count = total.min(SPORE_PARTICLES_MAX);
+    (0..count)
+        .map(|i| {
+            let amount = total / count + i32::from(i < total % count);
+            let angle = rng.gen_range(0.0..std::f32::consts::PI);
+            let speed = rng.gen_range(0.5..1.0) * SPORE_PARTICLE_BURST_SPEED;
+            (amount, Vec2::from_angle(angle) * speed)
+        })
+        .collect()
+}
+
+fn spawn_spore_burst(commands: &mut Commands, position: Vec3, total: i32, rng: &mut impl Rng) {
+    for (amount, velocity) in spore_burst(total, rng) {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SPORE_PARTICLE_COLOR,
+                    custom_size: Some(Vec2::splat(SPORE_PARTICLE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.truncate().extend(5.0)),
+                ..default()
+            },
+            SporeParticle {
+                amount,
+                velocity,
+                elapsed: 0.0,
+            },
+            RunEntity,
+        ));
+    }
+}
+
+fn spore_particle_system(
+    mut commands: Commands,
+    mut q_particle: Query<(Entity, &mut SporeParticle, &mut Transform)>,
+    q_spore_text: Query<&GlobalTransform, With<SporeText>>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    mut q_spores: Query<&mut Spores>,
+    time: Res<Time>,
+) {
+    let mut spores = q_spores.single_mut();
+    let (camera, camera_transform) = q_camera.single();
+    //UI nodes are laid out in window coordinates, the particles live in the world
+    let counter_position = q_spore_text.get_single().ok().and_then(|spore_text| {
+        camera.viewport_to_world_2d(camera_transform, spore_text.translation().truncate())
+    });
+    let delta = time.delta_seconds();
+
+    for (entity, mut particle, mut transform) in &mut q_particle {
+        particle.elapsed += delta;
+
+        if particle.elapsed < SPORE_PARTICLE_BURST_TIME {
+            transform.translation += (particle.velocity * delta).extend(0.0);
+            particle.velocity *= (1.0 - SPORE_PARTICLE_DRAG * delta).max(0.0);
+            continue;
+        }
+
+        let to_counter = counter_position.map_or(Vec2::ZERO, |counter_position| {
+            counter_position - transform.translation.truncate()
+        });
+        let step = SPORE_PARTICLE_SPEED * delta;
+        if to_counter.length() <= step {
+            spores.count += particle.amount;
+            commands.entity(entity).despawn();
+        } else {
+            transform.translation += (to_counter.normalize() * step).extend(0.0);
+        }
+    }
+}
+
+fn mushroom_base_regen_system(mut q_mushroom_base: Query<&mut MushroomBase>, time: Res<Time>) {
+    let mut mushroom_base = q_mushroom_base.single_mut();
+    if mushroom_base.hp <= 0.0 {
+        return;
+    }
+
+    mushroom_base.hp =
+        (mushroom_base.hp + mushroom_base.regen * time.delta_seconds()).min(mushroom_base.max_hp);
+}
+
+fn mushroom_base_death_system(
+    q_mushroom_base: Query<&MushroomBase>,
+    mut q_game_manager: Query<&mut GameManager>,
+) {
+    let mushroom_base = q_mushroom_base.single();
+    let mut game_manager = q_game_manager.single_mut();
+
+    if mushroom_base.hp <= 0.0 && !game_manager.game_over {
+        game_manager.game_over = true;
+        game_manager.victory = false;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn health_bar_system(
+    q_unit: Query<
+        (
+            Option<Ref<Hero>>,
+            Option<Ref<Mushroom>>,
+            Option<Ref<MushroomBase>>,
+            &Children,
+        ),
+        Or<(With<Hero>, With<Mushroom>, With<MushroomBase>)>,
+    >,
+    mut q_health_bar: Query<(&HealthBar, &mut Visibility, &Children)>,
+    mut q_health_bar_fill: Query<&mut Transform, With<HealthBarFill>>,
+    settings: Res<Settings>,
+) {
+    //Toggling the setting has to revisit bars whose hp didn't move
+    let refresh_all = settings.is_changed();
+
+    for (hero, mushroom, mushroom_base, children) in &q_unit {
+        let (hp, max_hp, changed) = match (hero, mushroom, mushroom_base) {
+            (Some(hero), _, _) => (hero.hp, hero.max_hp, hero.is_changed()),
+            (_, Some(mushroom), _) => (mushroom.hp, mushroom.max_hp, mushroom.is_changed()),
+            (_, _, Some(mushroom_base)) => (
+                mushroom_base.hp,
+                mushroom_base.max_hp,
+                mushroom_base.is_changed(),
+            ),
+            _ => continue,
+        };
+        if !changed && !refresh_all {
+            continue;
+        }
+
+        for &child in children.iter() {
+            let Ok((health_bar, mut visibility, bar_children)) = q_health_bar.get_mut(child) else {
+                continue;
+            };
+
+            let fraction = HealthBar::fraction(hp, max_hp);
+            let shown_visibility = if health_bar.is_shown(fraction, settings.full_health_bars) {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+            if *visibility != shown_visibility {
+                *visibility = shown_visibility;
+            }
+
+            for &fill in bar_children.iter() {
+                if let Ok(mut fill_transform) = q_health_bar_fill.get_mut(fill) {
+                    fill_transform.scale.x = fraction;
+                    fill_transform.translation.x = -health_bar.width * (1.0 - fraction) / 2.0;
+                }
+            }
+        }
+    }
+}
+
+fn hero_death_system(
+    mut commands: Commands,
+    mut q_hero: Query<
+        (
+            Entity,
+            &Hero,
+            &mut Unit,
+            &mut HeroTargets,
+            &mut StatusEffects,
+        ),
+        Without<DeathSequence>,
+    >,
+    q_telegraph: Query<(Entity, &HeroTelegraph)>,
+    q_splash_indicator: Query<(Entity, &SplashIndicator)>,
+    mut q_game_manager: Query<&mut GameManager>,
+) {
+    let mut game_manager = q_game_manager.single_mut();
+
+    for (hero_entity, hero, mut unit, mut hero_targets, mut status_effects) in &mut q_hero {
+        if hero.hp > 0.0 {
+            continue;
+        }
+
+        game_manager.heroes_killed += 1;
+        unit.die();
+        hero_targets.targets.clear();
+        status_effects.active.clear();
+        commands
+            .entity(hero_entity)
+            .insert(DeathSequence::default());
+        for (telegraph_entity, telegraph) in &q_telegraph {
+            if telegraph.hero == hero_entity {
+                commands.entity(telegraph_entity).despawn();
+            }
+        }
+        for (indicator_entity, splash_indicator) in &q_splash_indicator {
+            if splash_indicator.hero == hero_entity {
+                commands.entity(indicator_entity).despawn();
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_hero(
+    commands: &mut Commands,
+    image_manager: &ImageManager,
+    animation_manager: &AnimationManager,
+    class_definition: &HeroClassDefinition,
+    position: Vec3,
+    class: HeroClass,
+    level: i32,
+    modifiers: &RunModifiers,
+) {
+    let hero = commands
+        .spawn((
+            animation_manager.sprite_sheet(
+                class_definition.sheet,
+                Transform {
+                    translation: position,
+                    scale: (Vec3::splat(GLOBAL_SCALE)),
+                    ..default()
+                },
+                class_definition.tint(),
+            ),
+            class_definition.hero(class, level, modifiers),
+            HeroTargets::default(),
+            HeroAbilities::default(),
+            StatusEffects::default(),
+            SpriteTint {
+                color: class_definition.tint(),
+            },
+            Unit::new(HERO_SPAWN_TIME),
+            HitFlash::default(),
+            RunEntity,
         ))
-        .with_children(create_upgrade_button(
-            160.0,
-            75.0,
-            UpgradeType::AtkSpeed,
-            UPGRADE_MUSHROOM_ATK_SPEED_BASE_COST,
-            UPGRADE_COST_BASE_MULTIPLIER,
-            format!("ATK Speed: {MUSHROOM_BASE_ATK_SPEED}"),
-            upgrade_button_font_type.clone(),
+        .with_children(spawn_unit_health_bar)
+        .id();
+
+    commands.spawn((
+        SpriteBundle {
+            texture: image_manager[ImageType::SummonCircle].handle(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        HeroTelegraph { hero },
+        RunEntity,
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn encounter_system(
+    mut commands: Commands,
+    image_manager: Res<ImageManager>,
+    animation_manager: Res<AnimationManager>,
+    mut q_encounter_manager: Query<&mut EncounterManager>,
+    q_hero: Query<(), (With<Hero>, Without<DeathSequence>)>,
+    q_game_manager: Query<&GameManager>,
+    hero_class_manager: Res<HeroClassManager>,
+    q_battlefield: Query<&Battlefield>,
+    run_settings: Res<RunSettings>,
+    time: Res<Time>,
+) {
+    let mut encounter_manager = q_encounter_manager.single_mut();
+    let game_manager = q_game_manager.single();
+    if game_manager.game_over {
+        return;
+    }
+
+    let heroes_alive = !q_hero.is_empty();
+    let scheduled = encounter_manager.mode.scheduled_waves();
+    if !scheduled
+        && encounter_manager.wave_in_progress
+        && encounter_manager.spawn_queue.is_empty()
+        && !heroes_alive
+    {
+        encounter_manager.wave_in_progress = false;
+        encounter_manager.waves_cleared += 1;
+
+        match encounter_manager.upcoming_wave().map(|wave| wave.delay) {
+            Some(delay) => encounter_manager.wave_timer = delay,
+            None => encounter_manager.finished = true,
+        }
+    }
+
+    if encounter_manager.finished {
+        return;
+    }
+
+    if scheduled {
+        //The next wave turns up on time, however many heroes are still standing
+        encounter_manager.wave_timer -= time.delta_seconds();
+        if encounter_manager.wave_timer <= 0.0 {
+            //Outlasting a wave's slot counts as getting through it
+            if encounter_manager.next_wave > 0 {
+                encounter_manager.waves_cleared += 1;
+            }
+            encounter_manager.start_wave();
+            encounter_manager.wave_timer = encounter_manager
+                .upcoming_wave()
+                .map_or(f32::INFINITY, |wave| wave.delay);
+        }
+    } else if !encounter_manager.wave_in_progress {
+        encounter_manager.wave_timer -= time.delta_seconds();
+        if encounter_manager.wave_timer > 0.0 {
+            return;
+        }
+
+        if !encounter_manager.start_wave() {
+            encounter_manager.finished = true;
+            return;
+        }
+    }
+
+    let spawn_position = encounter_manager.spawn_position;
+    let battlefield = q_battlefield.single();
+    let modifiers = run_settings.modifiers();
+    let level = match encounter_manager.mode {
+        GameMode::Waves | GameMode::Campaign => HERO_BASE_LEVEL,
+        GameMode::Endless | GameMode::Daily => {
+            endless_hero_level(game_manager.run_time, game_manager.heroes_killed)
+        }
+    };
+    let encounter_manager = &mut *encounter_manager;
+    let rng = &mut encounter_manager.rng;
+    encounter_manager.spawn_queue.retain_mut(|(class, timer)| {
+        *timer -= time.delta_seconds();
+        if *timer > 0.0 {
+            return true;
+        }
+
+        let lane_y = battlefield.lanes[rng.gen_range(0..battlefield.lanes.len())];
+        spawn_hero(
+            &mut commands,
+            &image_manager,
+            &animation_manager,
+            &hero_class_manager[*class],
+            Vec3::new(spawn_position.x, lane_y, spawn_position.z),
+            *class,
+            level,
+            &modifiers,
+        );
+        false
+    });
+}
+
+fn endless_wave(waves_cleared: usize, classes: &[HeroClass], rng: &mut impl Rng) -> Wave {
+    let hero_count = ENDLESS_BASE_HERO_COUNT + waves_cleared / ENDLESS_WAVES_PER_EXTRA_HERO;
+
+    let mut class_counts = vec![0; classes.len()];
+    for _ in 0..hero_count {
+        class_counts[rng.gen_range(0..classes.len())] += 1;
+    }
+
+    Wave {
+        delay: ENDLESS_WAVE_DELAY,
+        groups: classes
+            .iter()
+            .zip(class_counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(&class, count)| WaveGroup {
+                class,
+                count,
+                interval: ENDLESS_HERO_SPAWN_INTERVAL,
+            })
+            .collect(),
+    }
+}
+
+fn endless_hero_level(run_time: f32, heroes_killed: u32) -> i32 {
+    let difficulty =
+        run_time / ENDLESS_SECONDS_PER_LEVEL + heroes_killed as f32 / ENDLESS_KILLS_PER_LEVEL;
+    HERO_BASE_LEVEL + difficulty as i32
+}
+
+//The hero furthest along the lane is the one shown on the HUD
+fn lead_hero<'a, T>(heroes: impl Iterator<Item = (T, &'a Transform)>) -> Option<T> {
+    heroes
+        .min_by(|(_, a), (_, b)| a.translation.x.total_cmp(&b.translation.x))
+        .map(|(hero, _)| hero)
+}
+
+#[allow(clippy::type_complexity)]
+fn mushroom_spawn_system(
+    mut q_spores: Query<&mut Spores>,
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+    q_mushroom: Query<
+        (),
+        (
+            With<Mushroom>,
+            Without<MushroomLord>,
+            Without<DeathSequence>,
+        ),
+    >,
+    q_battlefield: Query<&Battlefield>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    mouse: Res<Input<MouseButton>>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let mut spores = q_spores.single_mut();
+    let mut mushroom_manager = q_mushroom_manager.single_mut();
+
+    if !mushroom_manager.has_queue_space() {
+        return;
+    }
+
+    //Clicks near the cap or low on spores queue a smaller batch
+    let alive = q_mushroom.iter().count() as i32;
+    let spawn_count = mushroom_manager
+        .spawn_count
+        .min(spores.count)
+        .min(mushroom_manager.room(alive));
+    if spawn_count <= 0 {
+        return;
+    }
+
+    //Mushrooms go down the lane closest to the click
+    let battlefield = q_battlefield.single();
+    let (camera, camera_transform) = q_camera.single();
+    let lane = q_windows
+        .single()
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+        .map_or(0, |cursor| battlefield.nearest_lane(cursor.y));
+
+    spores.count -= spawn_count;
+    let order = SpawnOrder::new(
+        lane,
+        spawn_count,
+        mushroom_manager.formation,
+        mushroom_manager.mushroom_template,
+    );
+    mushroom_manager.queue.push_back(order);
+}
+
+//Grows the batch at the front of the queue and sends it out once it is done
+fn spawn_queue_system(
+    mut commands: Commands,
+    animation_manager: Res<AnimationManager>,
+    q_mushroom_base: Query<&Transform, With<MushroomBase>>,
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+    q_battlefield: Query<&Battlefield>,
+    time: Res<Time>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let mut mushroom_manager = q_mushroom_manager.single_mut();
+    if mushroom_manager.queue.is_empty() {
+        return;
+    }
+    let mushroom_manager = &mut *mushroom_manager;
+
+    let Some(order) = mushroom_manager.queue.front_mut() else {
+        return;
+    };
+    order.elapsed += time.delta_seconds();
+    if order.elapsed < order.build_time() {
+        return;
+    }
+    let Some(order) = mushroom_manager.queue.pop_front() else {
+        return;
+    };
+
+    let mushroom_base_position = q_mushroom_base.single().translation;
+    if let Some(tier) = order.lord_tier {
+        spawn_mushroom_lord(
+            &mut commands,
+            &animation_manager,
+            mushroom_base_position,
+            tier,
+            order.mushroom,
+        );
+        return;
+    }
+
+    let rng = &mut game_rng.0;
+    let lane_y = q_battlefield.single().lanes[order.lane];
+    let offsets = order.formation.offsets(order.count as usize, rng);
+    for offset in offsets {
+        let random_offset: f32 = rng.gen();
+        let lane_offset = rng.gen_range(-LANE_JITTER..=LANE_JITTER);
+
+        spawn_mushroom(
+            &mut commands,
+            &animation_manager,
+            &mut mushroom_manager.pool,
+            Vec3::new(
+                mushroom_base_position.x
+                    + random_offset * MUSHROOM_SPAWN_POSITION_OFFSET_AMOUNT
+                    + offset.x,
+                //Formation and jitter can't spill into the next lane
+                lane_y
+                    + (offset.y + lane_offset).clamp(
+                        -(LANE_SPACING / 2.0 - LANE_EDGE_MARGIN),
+                        LANE_SPACING / 2.0 - LANE_EDGE_MARGIN,
+                    ),
+                0.0,
+            ),
+            order.mushroom,
+        );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn spawn_queue_ui_system(
+    q_mushroom_manager: Query<&MushroomManager>,
+    q_mushroom: Query<
+        (),
+        (
+            With<Mushroom>,
+            Without<MushroomLord>,
+            Without<DeathSequence>,
+        ),
+    >,
+    mut q_population_text: Query<&mut Text, With<PopulationText>>,
+    mut q_slot: Query<(&SpawnQueueSlot, &mut Visibility, &Children)>,
+    mut q_fill: Query<&mut Style, With<SpawnQueueFill>>,
+    mut q_slot_text: Query<&mut Text, (With<SpawnQueueText>, Without<PopulationText>)>,
+) {
+    let mushroom_manager = q_mushroom_manager.single();
+    let alive = q_mushroom.iter().count() as i32;
+
+    q_population_text.single_mut().sections[0].value = format!(
+        "Population: {}/{}",
+        alive + mushroom_manager.queued(),
+        mushroom_manager.population_cap
+    );
+
+    for (slot, mut visibility, children) in &mut q_slot {
+        let Some(order) = mushroom_manager.queue.get(slot.index) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+
+        for &child in children.iter() {
+            if let Ok(mut style) = q_fill.get_mut(child) {
+                style.height = Val::Percent(order.progress() * 100.0);
+            }
+            if let Ok(mut text) = q_slot_text.get_mut(child) {
+                text.sections[0].value = match order.lord_tier {
+                    Some(_) => "Lord".to_string(),
+                    None => format!("x{}", order.count),
+                };
+            }
+        }
+    }
+}
+
+fn spawn_mushroom(
+    commands: &mut Commands,
+    animation_manager: &AnimationManager,
+    pool: &mut EntityPool,
+    position: Vec3,
+    mushroom: Mushroom,
+) {
+    let bundle = (
+        animation_manager.sprite_sheet(
+            AnimationSheet::Mushroom,
+            Transform {
+                translation: position,
+                scale: (Vec3::splat(GLOBAL_SCALE)),
+                ..default()
+            },
+            Color::WHITE,
+        ),
+        mushroom,
+        Unit::new(MUSHROOM_SPAWN_TIME),
+        HitFlash::default(),
+        StatusEffects::default(),
+    );
+
+    //A parked mushroom keeps its health bar, inserting the bundle again resets everything else
+    match pool.acquire() {
+        Some(entity) => {
+            commands.entity(entity).remove::<Pooled>().insert(bundle);
+        }
+        None => {
+            commands
+                .spawn((bundle, RunEntity))
+                .with_children(spawn_unit_health_bar);
+        }
+    }
+}
+
+fn spawn_mushroom_lord(
+    commands: &mut Commands,
+    animation_manager: &AnimationManager,
+    mushroom_base_position: Vec3,
+    tier_index: usize,
+    mushroom: Mushroom,
+) {
+    let tier = &MUSHROOM_LORD_TIERS[tier_index];
+    commands
+        .spawn((
+            animation_manager.sprite_sheet(
+                AnimationSheet::MushroomLord,
+                Transform {
+                    translation: Vec3::new(
+                        mushroom_base_position.x + MUSHROOM_SPAWN_POSITION_OFFSET_AMOUNT,
+                        mushroom_base_position.y + 32.0 * (tier.scale - 1.0),
+                        1.0,
+                    ),
+                    scale: (Vec3::splat(tier.scale)),
+                    ..default()
+                },
+                tier.tint,
+            ),
+            mushroom,
+            MushroomLord { tier: tier_index },
+            LordAbilities::default(),
+            SpriteTint { color: tier.tint },
+            Unit::new(MUSHROOM_SPAWN_TIME),
+            HitFlash::default(),
+            StatusEffects::default(),
+            RunEntity,
         ))
-        .with_children(create_upgrade_button(
-            180.0,
-            75.0,
-            UpgradeType::MoveSpeed,
-            UPGRADE_MUSHROOM_MOVE_SPEED_BASE_COST,
-            UPGRADE_COST_BASE_MULTIPLIER,
-            format!("Move Speed: {MUSHROOM_BASE_MOVE_SPEED}"),
-            upgrade_button_font_type.clone(),
+        .with_children(spawn_unit_health_bar);
+}
+
+//The summon only lands once the lord is out of the spawn queue
+#[allow(clippy::type_complexity)]
+fn mushroom_lord_arrival_system(
+    q_mushroom_lord: Query<(), Added<MushroomLord>>,
+    mut q_mushroom_status_effects: Query<&mut StatusEffects, With<Mushroom>>,
+    mut q_mushroom_lord_ui_visibility: Query<&mut Visibility, With<MushroomLordUI>>,
+    mut q_screen_shake: Query<&mut ScreenShake>,
+    settings: Res<Settings>,
+) {
+    if q_mushroom_lord.is_empty() {
+        return;
+    }
+
+    q_mushroom_status_effects.for_each_mut(|mut status_effects| {
+        status_effects.apply(MUSHROOM_LORD_SUMMON_EFFECT);
+    });
+    if settings.screen_shake {
+        q_screen_shake.single_mut().add(SCREEN_SHAKE_SUMMON_TRAUMA);
+    }
+    *q_mushroom_lord_ui_visibility.single_mut() = Visibility::Visible;
+}
+
+//Rebuilt at the start of every fixed tick, before anything on the tick moves or fights
+#[allow(clippy::type_complexity)]
+fn spatial_index_system(
+    mut spatial_index: ResMut<SpatialIndex>,
+    q_mushroom: Query<(Entity, &Transform), (With<Mushroom>, Without<DeathSequence>)>,
+    q_hero: Query<(Entity, &Transform), (With<Hero>, Without<DeathSequence>)>,
+) {
+    spatial_index.mushrooms.clear();
+    for (entity, transform) in &q_mushroom {
+        spatial_index
+            .mushrooms
+            .insert(battlefield_position(transform), entity);
+    }
+
+    spatial_index.heroes.clear();
+    for (entity, transform) in &q_hero {
+        spatial_index
+            .heroes
+            .insert(battlefield_position(transform), entity);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn y_sort_system(mut q_unit: Query<&mut Transform, Or<(With<Hero>, With<Mushroom>)>>) {
+    for mut transform in &mut q_unit {
+        let z = UNIT_Z - battlefield_position(&transform).y * Y_SORT_DEPTH_PER_PIXEL;
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn mushroom_movement_system(
+    mut q_mushroom: Query<(
+        Entity,
+        &mut Transform,
+        &Mushroom,
+        &Unit,
+        &StatusEffects,
+        Option<&MushroomLord>,
+    )>,
+    q_battlefield: Query<&Battlefield>,
+    q_encounter_manager: Query<&EncounterManager>,
+    spatial_index: Res<SpatialIndex>,
+    mut agents: Local<HashMap<Entity, CrowdAgent>>,
+    mut order: Local<Vec<Entity>>,
+    mut steering: Local<HashMap<Entity, (Vec2, bool)>>,
+    time: Res<Time>,
+) {
+    let battlefield = q_battlefield.single();
+    //Heroes walk in from here, nothing past it is worth walking to
+    let march_limit = q_encounter_manager.single().spawn_position.x;
+
+    agents.clear();
+    for (entity, transform, _, unit, _, lord) in &q_mushroom {
+        if unit.state == UnitState::Dying {
+            continue;
+        }
+        agents.insert(
+            entity,
+            CrowdAgent {
+                position: battlefield_position(transform),
+                stopped: unit.state != UnitState::Advancing,
+                in_crowd: lord.is_none(),
+            },
+        );
+    }
+
+    //Front to back, so a blocked mushroom can block the one behind it in the same tick
+    order.clear();
+    order.extend(agents.keys().copied());
+    order.sort_by(|a, b| agents[b].position.x.total_cmp(&agents[a].position.x));
+    steering.clear();
+    for &entity in order.iter() {
+        let (push, blocked) = crowd_steering(&agents, &spatial_index.mushrooms, entity);
+        if let Some(agent) = agents.get_mut(&entity) {
+            agent.stopped |= blocked;
+        }
+        steering.insert(entity, (push, blocked));
+    }
+
+    for (entity, mut transform, mushroom, unit, status_effects, lord) in &mut q_mushroom {
+        let (Some(&(push, blocked)), Some(agent)) = (steering.get(&entity), agents.get(&entity))
+        else {
+            continue;
+        };
+        let position = agent.position;
+        let mut velocity = push * SEPARATION_SPEED;
+        let mut changing_lanes = false;
+        if unit.state == UnitState::Advancing {
+            if !blocked && position.x < march_limit {
+                velocity.x += mushroom.move_speed * status_effects.move_speed_multiplier();
+            }
+            let nearest_hero = spatial_index.heroes.nearest(position, f32::INFINITY);
+            if let Some(direction) = nearest_hero
+                .and_then(|(hero_position, _)| lane_change(battlefield, position, hero_position))
+            {
+                velocity.y += direction * LANE_CHANGE_SPEED;
+                changing_lanes = true;
+            }
+        }
+        if velocity == Vec2::ZERO {
+            continue;
+        }
+
+        transform.translation += (velocity * time.delta_seconds()).extend(0.0);
+        transform.translation.x = transform.translation.x.min(march_limit.max(position.x));
+        if lord.is_none() && !changing_lanes {
+            transform.translation.y = clamp_to_lane(battlefield, transform.translation.y);
+        }
+    }
+}
+
+fn formation_input_system(
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    if !keyboard.just_pressed(FORMATION_CYCLE_KEY) {
+        return;
+    }
+
+    let mut mushroom_manager = q_mushroom_manager.single_mut();
+    mushroom_manager.formation = mushroom_manager.formation.next();
+}
+
+fn formation_text_update_system(
+    mut q_formation_text: Query<&mut Text, With<FormationText>>,
+    q_mushroom_manager: Query<&MushroomManager, Changed<MushroomManager>>,
+) {
+    let Ok(mushroom_manager) = q_mushroom_manager.get_single() else {
+        return;
+    };
+
+    q_formation_text.single_mut().sections[0].value =
+        format!("Formation: {} [F]", mushroom_manager.formation.name());
+}
+
+#[allow(clippy::type_complexity)]
+fn mushroom_attack_system(
+    mut q_mushroom: Query<
+        (
+            &Mushroom,
+            &Transform,
+            &mut Unit,
+            &StatusEffects,
+            &mut Animation,
+        ),
+        (Without<Hero>, Without<DeathSequence>),
+    >,
+    spatial_index: Res<SpatialIndex>,
+    animation_manager: Res<AnimationManager>,
+    time: Res<Time>,
+) {
+    q_mushroom.for_each_mut(|mushroom_data| {
+        let mushroom_transform = mushroom_data.1;
+        let mushroom = mushroom_data.0;
+        let mut unit = mushroom_data.2;
+        let status_effects = mushroom_data.3;
+        let mut animation = mushroom_data.4;
+
+        let mushroom_position = battlefield_position(mushroom_transform);
+        let target_in_range = spatial_index
+            .heroes
+            .nearest(mushroom_position, mushroom.atk_range)
+            .is_some();
+        let senses = UnitSenses {
+            target_in_range,
+            stunned: status_effects.is_stunned(),
+            dead: mushroom.hp <= 0.0,
+            busy: false,
+            attack_finished: !animation.is_playing(AnimationClip::Attack),
+        };
+
+        //The hit itself lands on the impact frame, see mushroom_attack_impact_system
+        if unit.update(&senses, time.delta_seconds()) == Some(UnitState::Attacking) {
+            let cooldown = 1.0 / (mushroom.atk_speed * status_effects.atk_speed_multiplier());
+            let clip = animation_manager.clip(animation.sheet, AnimationClip::Attack);
+            animation.restart(AnimationClip::Attack, (clip.duration() / cooldown).max(1.0));
+            unit.attack_cooldown = cooldown;
+        }
+    });
+}
+
+//Stays in Update with the animation events it reacts to, fixed ticks can miss those,
+//so it reads the index as the last fixed tick left it
+#[allow(clippy::type_complexity)]
+fn mushroom_attack_impact_system(
+    mut animation_events: EventReader<AnimationEvent>,
+    mut q_hero: Query<
+        (Entity, &mut Hero, &Transform, &mut StatusEffects),
+        (Without<Mushroom>, Without<DeathSequence>),
+    >,
+    q_mushroom: Query<(&Mushroom, &Transform, &StatusEffects, &Unit), Without<Hero>>,
+    spatial_index: Res<SpatialIndex>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let rng = &mut game_rng.0;
+
+    for animation_event in animation_events.read() {
+        if animation_event.event != FrameEvent::Impact {
+            continue;
+        }
+        let Ok((mushroom, mushroom_transform, status_effects, unit)) =
+            q_mushroom.get(animation_event.entity)
+        else {
+            continue;
+        };
+        if unit.state != UnitState::Attacking {
+            continue;
+        }
+        let mushroom_position = battlefield_position(mushroom_transform);
+        let Some((_, hero_entity)) = spatial_index
+            .heroes
+            .nearest(mushroom_position, mushroom.atk_range)
+        else {
+            continue;
+        };
+
+        let Ok((_, mut hero, hero_transform, mut hero_status_effects)) =
+            q_hero.get_mut(hero_entity)
+        else {
+            continue;
+        };
+        let attack = mushroom.attack(mushroom.atk * status_effects.atk_multiplier());
+        let damage = resolve_damage(&attack, &hero.defense(), rng);
+        hero.hp -= damage.amount;
+        if let Some(on_hit_effect) = mushroom.on_hit_effect {
+            hero_status_effects.apply(on_hit_effect);
+        }
+        damage_events.send(DamageEvent::new(
+            hero_entity,
+            hero_transform.translation,
+            &attack,
+            &damage,
         ));
+    }
+}
 
-    //Summon commands
-    commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(100.0),
-                height: Val::Percent(100.0),
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::Center,
-                top: Val::Percent(37.5),
+#[allow(clippy::type_complexity)]
+fn status_effect_update_system(
+    mut q_status_effects: Query<(
+        Entity,
+        &Transform,
+        &mut StatusEffects,
+        Option<&mut Hero>,
+        Option<&mut Mushroom>,
+    )>,
+    time: Res<Time>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let mut rng = &mut game_rng.0;
+
+    q_status_effects.for_each_mut(
+        |(entity, transform, mut status_effects, mut hero, mut mushroom)| {
+            let mut poison_damage = 0.0;
+
+            for active in status_effects.active.iter_mut() {
+                active.remaining -= time.delta_seconds();
+                active.tick_timer -= time.delta_seconds();
+
+                if active.tick_timer > 0.0 {
+                    continue;
+                }
+                active.tick_timer += STATUS_EFFECT_TICK_INTERVAL;
+
+                if active.effect.kind == StatusEffectKind::Poison {
+                    poison_damage += active.effect.magnitude
+                        * active.stacks as f32
+                        * STATUS_EFFECT_TICK_INTERVAL;
+                }
+            }
+
+            status_effects
+                .active
+                .retain(|active| active.remaining > 0.0);
+
+            if poison_damage <= 0.0 {
+                return;
+            }
+
+            let poison = Attack {
+                amount: poison_damage,
+                damage_type: DamageType::Toxic,
+                crit_chance: 0.0,
+                crit_multiplier: 1.0,
+            };
+            let damage = if let Some(hero) = hero.as_mut() {
+                let damage = resolve_damage(&poison, &hero.defense(), &mut rng);
+                hero.hp -= damage.amount;
+                damage
+            } else if let Some(mushroom) = mushroom.as_mut() {
+                let damage = resolve_damage(&poison, &mushroom.defense(), &mut rng);
+                mushroom.hp -= damage.amount;
+                damage
+            } else {
+                return;
+            };
+            damage_events.send(DamageEvent::new(
+                entity,
+                transform.translation,
+                &poison,
+                &damage,
+            ));
+        },
+    );
+}
+
+fn animation_system(
+    mut q_animation: Query<(Entity, &mut Animation, &mut TextureAtlasSprite)>,
+    mut animation_events: EventWriter<AnimationEvent>,
+    animation_manager: Res<AnimationManager>,
+    time: Res<Time>,
+) {
+    for (entity, mut animation, mut sprite) in &mut q_animation {
+        let clip = animation_manager.clip(animation.sheet, animation.clip);
+        for event in animation.advance(clip, time.delta_seconds()) {
+            animation_events.send(AnimationEvent { entity, event });
+        }
+        sprite.index = animation.atlas_index(clip);
+    }
+}
+
+//The clip follows the unit state, getting hit flinches a unit that isn't mid swing
+fn unit_animation_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut q_unit: Query<(&mut Animation, &Unit)>,
+) {
+    for damage_event in damage_events.read() {
+        if let Ok((mut animation, unit)) = q_unit.get_mut(damage_event.target) {
+            if matches!(unit.state, UnitState::Advancing | UnitState::Engaging) {
+                animation.restart(AnimationClip::Hurt, 1.0);
+            }
+        }
+    }
+
+    for (mut animation, unit) in &mut q_unit {
+        match unit.state {
+            //Started by the attack systems when the unit starts swinging
+            UnitState::Attacking => {}
+            UnitState::Dying => animation.play(AnimationClip::Die),
+            UnitState::Spawning { .. } | UnitState::Stunned => animation.play(AnimationClip::Idle),
+            UnitState::Advancing | UnitState::Engaging => {
+                if !animation.is_playing(AnimationClip::Hurt) {
+                    animation.play(if unit.state == UnitState::Advancing {
+                        AnimationClip::Walk
+                    } else {
+                        AnimationClip::Idle
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn death_sequence_system(
+    mut commands: Commands,
+    mut q_dying: Query<(
+        Entity,
+        &mut DeathSequence,
+        &Animation,
+        &mut TextureAtlasSprite,
+        Option<&Mushroom>,
+        Option<&MushroomLord>,
+    )>,
+    animation_manager: Res<AnimationManager>,
+    time: Res<Time>,
+) {
+    for (entity, mut death_sequence, animation, mut sprite, mushroom, lord) in &mut q_dying {
+        death_sequence.elapsed += time.delta_seconds();
+
+        let die_time = animation_manager
+            .clip(animation.sheet, AnimationClip::Die)
+            .duration();
+        let fade = ((death_sequence.elapsed - die_time) / CORPSE_FADE_TIME).clamp(0.0, 1.0);
+        sprite.color.set_a(1.0 - fade);
+
+        if fade < 1.0 {
+            continue;
+        }
+
+        //Plain mushrooms are stripped down to be parked, mushroom_pool_system hands them
+        //to the pool once the strip has applied
+        if mushroom.is_some() && lord.is_none() {
+            commands
+                .entity(entity)
+                .remove::<(
+                    Mushroom,
+                    Unit,
+                    DeathSequence,
+                    Animation,
+                    TextureAtlasSprite,
+                    StatusEffects,
+                    HitFlash,
+                )>()
+                .insert((Pooled, Visibility::Hidden));
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+//A frame late on purpose, releasing in death_sequence_system would let a spawner acquire
+//the mushroom before its old components are gone
+fn mushroom_pool_system(
+    mut commands: Commands,
+    q_pooled: Query<Entity, Added<Pooled>>,
+    mut q_mushroom_manager: Query<&mut MushroomManager>,
+) {
+    let mut mushroom_manager = q_mushroom_manager.single_mut();
+
+    for entity in &q_pooled {
+        if !mushroom_manager.pool.release(entity) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn status_effect_tint_system(
+    mut q_sprite: Query<(
+        &mut TextureAtlasSprite,
+        &StatusEffects,
+        Option<&SpriteTint>,
+        Option<&HitFlash>,
+    )>,
+) {
+    q_sprite.for_each_mut(|(mut sprite, status_effects, sprite_tint, hit_flash)| {
+        let base_color = sprite_tint.map_or(Color::WHITE, |tint| tint.color);
+        sprite.color = if hit_flash.is_some_and(|hit_flash| hit_flash.remaining > 0.0) {
+            HIT_FLASH_COLOR
+        } else {
+            status_effects.tint().unwrap_or(base_color)
+        };
+    });
+}
+
+fn damage_number_system(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+) {
+    if !settings.damage_numbers {
+        damage_events.clear();
+        return;
+    }
+
+    for damage_event in damage_events.read() {
+        let font_size = if damage_event.is_crit {
+            FLOATING_TEXT_CRIT_FONT_SIZE
+        } else {
+            FLOATING_TEXT_FONT_SIZE
+        };
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    damage_event.text(),
+                    TextStyle {
+                        font: asset_server.load("./fonts/Roboto-Regular.ttf"),
+                        font_size,
+                        color: damage_event.color(),
+                    },
+                ),
+                transform: Transform::from_translation(
+                    damage_event.position.truncate().extend(10.0)
+                        + Vec3::Y * FLOATING_TEXT_OFFSET_Y,
+                ),
                 ..default()
             },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent.spawn((
-                ButtonBundle {
-                    style: Style {
-                        width: Val::Px(128.0),
-                        height: Val::Px(128.0),
-                        flex_direction: FlexDirection::Column,
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    image: UiImage {
-                        texture: summoning_circle_image,
-                        ..default()
-                    },
-                    background_color: SUMMON_BUTTON_INACTIVE_COLOR,
-                    ..default()
-                },
-                SummonButton,
-            ));
-        });
+            FloatingText { elapsed: 0.0 },
+            RunEntity,
+        ));
+    }
 }
 
-fn setup_system(
+fn floating_text_system(
     mut commands: Commands,
-    image_manager: Res<ImageManager>,
-    q_windows: Query<&Window, With<PrimaryWindow>>,
+    mut q_floating_text: Query<(Entity, &mut FloatingText, &mut Transform, &mut Text)>,
+    time: Res<Time>,
 ) {
-    let mushroom_base_sprite = &image_manager[ImageType::MushroomBase];
-    let ground_sprite = &image_manager[ImageType::Ground];
-    let hero_sprite = &image_manager[ImageType::Hero];
+    for (entity, mut floating_text, mut transform, mut text) in &mut q_floating_text {
+        floating_text.elapsed += time.delta_seconds();
+        if floating_text.elapsed >= FLOATING_TEXT_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
 
-    let window = q_windows.single();
-    let width = window.width();
-    let height = window.height();
+        transform.translation.y += FLOATING_TEXT_SPEED * time.delta_seconds();
+        let alpha = 1.0 - floating_text.elapsed / FLOATING_TEXT_LIFETIME;
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
+    }
+}
 
-    let tile_x_count = (width / TILE_SIZE) as i32;
-    let tile_y_count = ((height / TILE_SIZE) / 2.0) as i32;
+fn hit_flash_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut q_hit_flash: Query<&mut HitFlash>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    for mut hit_flash in &mut q_hit_flash {
+        hit_flash.remaining -= time.delta_seconds();
+    }
 
-    commands.spawn((Camera2dBundle::default(), GameCamera));
+    for damage_event in damage_events.read() {
+        if !settings.hit_flash {
+            continue;
+        }
+        if let Ok(mut hit_flash) = q_hit_flash.get_mut(damage_event.target) {
+            hit_flash.remaining = HIT_FLASH_TIME;
+        }
+    }
+}
 
-    let tile_size = TILE_SIZE as i32;
-    let x_offset = width / 2.0;
-    let y_offset = height / 2.0;
+fn screen_shake_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut q_camera: Query<(&mut ScreenShake, &mut Transform), With<GameCamera>>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let (mut screen_shake, mut camera_transform) = q_camera.single_mut();
+
+    for damage_event in damage_events.read() {
+        if settings.screen_shake && damage_event.amount >= SCREEN_SHAKE_MIN_DAMAGE {
+            screen_shake.add(SCREEN_SHAKE_HIT_TRAUMA);
+        }
+    }
+    if !settings.screen_shake {
+        screen_shake.trauma = 0.0;
+    }
+
+    screen_shake.trauma =
+        (screen_shake.trauma - SCREEN_SHAKE_DECAY * time.delta_seconds()).max(0.0);
+
+    //Cosmetic, so it stays off the run's seeded rng
+    let mut rng = rand::thread_rng();
+    let offset = screen_shake.trauma.powi(2) * SCREEN_SHAKE_MAX_OFFSET;
+    camera_transform.translation.x = rng.gen_range(-1.0..=1.0) * offset;
+    camera_transform.translation.y = rng.gen_range(-1.0..=1.0) * offset;
+}
+
+fn spore_text_update_system(
+    mut q_spore_text: Query<&mut Text, With<SporeText>>,
+    q_spores: Query<&Spores>,
+) {
+    let mut text = q_spore_text.single_mut();
+    let spore_count = q_spores.single().count;
+
+    text.sections[0].value = format!("Spores: {spore_count}");
+}
+
+#[allow(clippy::single_match)]
+fn mushroom_lord_ui_update_system(
+    mut q_mushroom_lord_hp_text: Query<&mut Text, With<MushroomLordHPText>>,
+    mut q_mushroom_lord_tier_text: Query<
+        &mut Text,
+        (With<MushroomLordTierText>, Without<MushroomLordHPText>),
+    >,
+    q_mushroom_lord: Query<(&Mushroom, &MushroomLord), Without<DeathSequence>>,
+) {
+    let q_mushroom_lord_result = q_mushroom_lord.get_single();
+    match q_mushroom_lord_result {
+        Ok((mushroom_lord, lord)) => {
+            let mut mushroom_lord_hp_text = q_mushroom_lord_hp_text.single_mut();
+            let mut mushroom_lord_tier_text = q_mushroom_lord_tier_text.single_mut();
+            let hp = mushroom_lord.hp;
+
+            mushroom_lord_hp_text.sections[0].value = format!("Mushroom Lord HP: {hp}");
+            mushroom_lord_tier_text.sections[0].value =
+                MUSHROOM_LORD_TIERS[lord.tier].name.to_string();
+        }
+        Err(_) => (),
+    }
+}
+
+fn wave_text_update_system(
+    mut q_wave_text: Query<&mut Text, With<WaveText>>,
+    q_encounter_manager: Query<&EncounterManager>,
+    q_hero: Query<(), (With<Hero>, Without<DeathSequence>)>,
+) {
+    let mut text = q_wave_text.single_mut();
+    let encounter_manager = q_encounter_manager.single();
+    let wave = match encounter_manager.mode {
+        GameMode::Waves | GameMode::Campaign => format!(
+            "{}/{}",
+            encounter_manager.next_wave,
+            encounter_manager.waves.len()
+        ),
+        GameMode::Endless | GameMode::Daily => {
+            format!("{} (endless)", encounter_manager.next_wave)
+        }
+    };
+    let hero_count = q_hero.iter().count() + encounter_manager.spawn_queue.len();
+
+    let timer = encounter_manager.wave_timer.max(0.0).ceil();
+
+    text.sections[0].value = if encounter_manager.mode.scheduled_waves() {
+        format!("Wave: {wave} ({hero_count} heroes, next in {timer}s)")
+    } else if encounter_manager.wave_in_progress {
+        format!("Wave: {wave} ({hero_count} heroes left)")
+    } else {
+        format!("Wave: {wave} (next in {timer}s)")
+    };
+}
+
+fn objective_text_update_system(
+    mut q_objective_text: Query<&mut Text, With<ObjectiveText>>,
+    q_game_manager: Query<&GameManager, Changed<GameManager>>,
+    run_settings: Res<RunSettings>,
+) {
+    let Ok(game_manager) = q_game_manager.get_single() else {
+        return;
+    };
+    let mut text = q_objective_text.single_mut();
+
+    let goals: Vec<String> = game_manager
+        .win_conditions
+        .iter()
+        .map(WinCondition::description)
+        .collect();
+    text.sections[0].value = if goals.is_empty() {
+        "Goal: Survive as long as you can".to_string()
+    } else {
+        format!("Goal: {}", goals.join(" or "))
+    };
+    if let Some(daily) = &run_settings.daily {
+        text.sections[0].value += &format!("\nDaily {} (seed {})", daily.date, run_settings.seed);
+    }
+}
+
+fn hero_hp_text_update_system(
+    mut q_hero_hp_text: Query<&mut Text, With<HeroHPText>>,
+    q_hero: Query<(&Hero, &Transform)>,
+) {
+    let mut text = q_hero_hp_text.single_mut();
+
+    text.sections[0].value = match lead_hero(q_hero.iter()) {
+        Some(hero) => format!("{:?} HP: {}", hero.class, hero.hp),
+        None => "Hero HP: -".to_string(),
+    };
+}
+
+fn hero_exp_text_update_system(
+    mut q_hero_exp_text: Query<&mut Text, With<HeroEXPText>>,
+    q_hero: Query<(&Hero, &Transform)>,
+) {
+    let mut text = q_hero_exp_text.single_mut();
+
+    text.sections[0].value = match lead_hero(q_hero.iter()) {
+        Some(hero) => format!("Hero EXP: {:.0}/{:.0}", hero.exp, hero.next_level_exp),
+        None => "Hero EXP: -".to_string(),
+    };
+}
+
+fn hero_level_text_update_system(
+    mut q_hero_level_text: Query<&mut Text, With<HeroLevelText>>,
+    q_hero: Query<(&Hero, &Transform)>,
+) {
+    let mut text = q_hero_level_text.single_mut();
+
+    text.sections[0].value = match lead_hero(q_hero.iter()) {
+        Some(hero) => format!("Hero Level: {}", hero.level),
+        None => "Hero Level: -".to_string(),
+    };
+}
+
+fn hero_targeting_text_update_system(
+    mut q_hero_targeting_text: Query<&mut Text, With<HeroTargetingText>>,
+    q_hero: Query<&Hero, (With<SelectedHero>, Without<DeathSequence>)>,
+) {
+    let mut text = q_hero_targeting_text.single_mut();
+
+    text.sections[0].value = match q_hero.get_single() {
+        Ok(hero) => format!("Hero Targeting: {} [T]", hero.targeting.name()),
+        Err(_) => "Hero Targeting: right click a hero".to_string(),
+    };
+}
+
+fn hero_phase_text_update_system(
+    mut q_hero_phase_text: Query<&mut Text, With<HeroPhaseText>>,
+    q_hero: Query<(&Hero, &HeroAbilities, &Transform)>,
+) {
+    let mut text = q_hero_phase_text.single_mut();
+    let lead_hero = lead_hero(
+        q_hero
+            .iter()
+            .map(|(hero, abilities, transform)| ((hero, abilities), transform)),
+    );
+    let Some((hero, abilities)) = lead_hero else {
+        text.sections[0].value = "Hero Phase: -".to_string();
+        return;
+    };
+    let phase = hero.phase + 1;
 
-    //Ground
-    for i in 0..tile_x_count + 1 {
-        for j in 0..tile_y_count {
-            let x = (i * tile_size) as f32 - x_offset;
-            let y = (j * tile_size) as f32 - y_offset;
+    text.sections[0].value = match abilities.casting {
+        Some((ability, _)) => format!("Hero Phase: {phase} (casting {ability:?})"),
+        None => format!("Hero Phase: {phase}"),
+    };
+}
 
-            commands.spawn((
-                SpriteBundle {
-                    transform: Transform {
-                        translation: Vec3::new(x, y, 0.0),
-                        scale: (Vec3::splat(1.0)),
-                        ..default()
-                    },
-                    texture: ground_sprite.handle(),
-                    ..default()
-                },
-                Ground,
-            ));
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_state::<AppState>()
+        .add_event::<HeroLevelUpEvent>()
+        .add_event::<AnimationEvent>()
+        .add_event::<DamageEvent>()
+        .init_resource::<RunSettings>()
+        .init_resource::<SpatialIndex>()
+        .insert_resource(Settings::load())
+        .insert_resource(GameRng(StdRng::from_entropy()))
+        .insert_resource::<Campaign>(ron::from_str(CAMPAIGN).expect("invalid campaign"))
+        .insert_resource::<ImageManager>(ImageManager {
+            images: HashMap::new(),
+        })
+        .insert_resource::<HeroClassManager>(
+            ron::from_str(HERO_CLASSES).expect("invalid hero class definitions"),
+        )
+        .insert_resource::<AnimationManager>(
+            ron::from_str(ANIMATIONS).expect("invalid animation definitions"),
+        )
+        .add_systems(PreStartup, load_assets_system)
+        .add_systems(Startup, setup_camera_system)
+        .add_systems(OnEnter(AppState::Menu), setup_menu_system)
+        .add_systems(OnExit(AppState::Menu), cleanup_menu_system)
+        .add_systems(OnEnter(AppState::LevelSelect), setup_level_select_system)
+        .add_systems(OnExit(AppState::LevelSelect), cleanup_level_select_system)
+        .add_systems(OnEnter(AppState::RunSetup), setup_run_setup_system)
+        .add_systems(OnExit(AppState::RunSetup), cleanup_run_setup_system)
+        .add_systems(OnEnter(AppState::InGame), (setup_system, setup_ui_system))
+        .add_systems(OnExit(AppState::InGame), cleanup_run_system)
+        .add_systems(
+            Update,
+            (
+                button_system,
+                mode_button_system,
+                level_button_system.after(button_system),
+                run_setup_button_system.after(button_system),
+                back_to_menu_button_system,
+                setting_button_system,
+                screen_shake_system,
+            ),
+        )
+        .add_systems(
+            PreUpdate,
+            status_effect_update_system.run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                spatial_index_system,
+                (
+                    hero_movement_system,
+                    hero_attack_system,
+                    hero_ability_system,
+                    mushroom_movement_system,
+                    mushroom_attack_system,
+                    mushroom_death_system,
+                    mushroom_lord_ability_system,
+                ),
+            )
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            Update,
+            (
+                //MUSHROOM
+                mushroom_spawn_system,
+                spawn_queue_system.after(mushroom_spawn_system),
+                spawn_queue_ui_system.after(spawn_queue_system),
+                formation_input_system,
+                formation_text_update_system,
+                mushroom_attack_impact_system.after(animation_system),
+                mushroom_lord_ui_update_system,
+                mushroom_lord_arrival_system,
+                mushroom_lord_ability_input_system,
+                mushroom_lord_ability_text_update_system,
+                spore_text_update_system,
+                //MUSHROOM BASE
+                mushroom_base_regen_system,
+                mushroom_base_death_system,
+                health_bar_system,
+                //BATTLEFIELD
+                y_sort_system,
+            )
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            Update,
+            (
+                //HERO
+                hero_hp_text_update_system,
+                hero_exp_text_update_system,
+                hero_level_text_update_system,
+                hero_targeting_text_update_system,
+                hero_selection_system,
+                hero_targeting_input_system.after(hero_selection_system),
+                hero_target_marker_system,
+                hero_attack_impact_system.after(animation_system),
+                hero_phase_text_update_system,
+                hero_telegraph_system,
+                hero_phase_system,
+                hero_exp_system,
+                hero_level_up_system.after(hero_exp_system),
+                hero_death_system,
+                encounter_system,
+                wave_text_update_system,
+            )
+                .run_if(in_state(AppState::InGame)),
+        )
+        .add_systems(
+            Update,
+            (
+                //STATUS EFFECTS
+                status_effect_tint_system,
+                //ANIMATION
+                unit_animation_system.before(animation_system),
+                animation_system,
+                death_sequence_system.after(status_effect_tint_system),
+                mushroom_pool_system,
+                spore_particle_system,
+                //COMBAT FEEDBACK
+                hit_flash_system.before(status_effect_tint_system),
+                damage_number_system,
+                floating_text_system,
+                //UI
+                upgrade_button_system,
+                summon_button_system,
+                sacrifice_button_system,
+                summon_preview_system,
+                summon_cooldown_system,
+                summon_status_text_update_system,
+                game_over_system,
+                run_stats_system,
+                spore_decay_system,
+                win_condition_system,
+                objective_text_update_system,
+            )
+                .run_if(in_state(AppState::InGame)),
+        )
+        .run();
+}
+
+//Headless mushroom churn through the real spawn and death path, benches/mushroom_pool.rs
+//pulls this file in as a module to time it
+#[allow(dead_code)]
+pub mod churn {
+    use super::*;
+
+    //Roughly a late wave, a few dozen mushrooms spawning and dying every frame
+    pub const POPULATION: usize = 2000;
+    pub const SPAWNS_PER_FRAME: usize = 40;
+    pub const POOL_CAPACITY: usize = MUSHROOM_POOL_CAPACITY;
+
+    //Kills the oldest mushrooms past the population, the die clip and fade are skipped
+    fn kill_system(
+        mut commands: Commands,
+        q_mushroom: Query<Entity, (With<Mushroom>, Without<DeathSequence>)>,
+    ) {
+        let excess = (q_mushroom.iter().len() + SPAWNS_PER_FRAME).saturating_sub(POPULATION);
+        for entity in q_mushroom.iter().take(excess) {
+            commands
+                .entity(entity)
+                .insert(DeathSequence { elapsed: f32::MAX });
         }
     }
 
-    //Background
-    commands.spawn((SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(0.0, 0.0, -1.0),
-            scale: (Vec3::splat(1.0)),
-            ..default()
-        },
-        texture: image_manager[ImageType::Background].handle(),
-        ..default()
-    },));
-
-    let initial_height = -y_offset + (tile_y_count as f32) * TILE_SIZE;
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(x_offset - TILE_SIZE, initial_height, 0.0),
-                scale: (Vec3::splat(GLOBAL_SCALE)),
-                ..default()
-            },
-            texture: hero_sprite.handle(),
-            ..default()
-        },
-        Hero {
-            hp: HERO_BASE_HP,
-            atk: HERO_BASE_ATK,
-            move_speed: HERO_BASE_MOVE_SPEED,
-            atk_speed: HERO_BASE_ATK_SPEED,
-            atk_range: HERO_BASE_ATK_RANGE,
-            level: HERO_BASE_LEVEL,
-            exp: 0.0,
-            next_level_exp: HERO_BASE_EXP_REQUIRED,
-        },
-        AttackTimer { value: 0.0 },
-        InCombat { value: false },
-    ));
+    fn spawn_system(
+        mut commands: Commands,
+        animation_manager: Res<AnimationManager>,
+        mut q_mushroom_manager: Query<&mut MushroomManager>,
+    ) {
+        let mut mushroom_manager = q_mushroom_manager.single_mut();
+        let mushroom = mushroom_manager.mushroom_template;
+        for _ in 0..SPAWNS_PER_FRAME {
+            spawn_mushroom(
+                &mut commands,
+                &animation_manager,
+                &mut mushroom_manager.pool,
+                Vec3::ZERO,
+                mushroom,
+            );
+        }
+    }
 
-    commands.spawn(Spores {
-        count: INITIAL_SPORE_COUNT,
-    });
+    //No plugins and no atlases loaded, everything else is what the game runs
+    pub fn app(pool_capacity: usize) -> App {
+        let mut animation_manager: AnimationManager =
+            ron::from_str(ANIMATIONS).expect("invalid animation definitions");
+        for &sheet in animation_manager.sheets.keys() {
+            animation_manager.atlases.insert(sheet, Handle::default());
+        }
 
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(-x_offset + TILE_SIZE, initial_height, 0.0),
-                scale: (Vec3::splat(GLOBAL_SCALE)),
-                ..default()
-            },
-            texture: mushroom_base_sprite.handle(),
+        let mut app = App::new();
+        app.insert_resource(animation_manager)
+            .init_resource::<Time>()
+            .init_resource::<SpatialIndex>()
+            .add_systems(
+                Update,
+                (
+                    kill_system,
+                    spawn_system,
+                    spatial_index_system,
+                    death_sequence_system,
+                    mushroom_pool_system,
+                    y_sort_system,
+                )
+                    .chain(),
+            );
+        app.world.spawn(MushroomManager {
+            pool: EntityPool::new(pool_capacity),
             ..default()
-        },
-        MushroomBase,
-    ));
+        });
+        app
+    }
+}
 
-    commands.spawn(MushroomManager {
-        ..Default::default()
-    });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
 
-    commands.spawn(SummonManager { ..default() });
-    commands.spawn(GameManager::default());
-}
+    const SEED: u64 = 55;
 
-fn upgrade_button_system(
-    mut q_interaction: Query<
-        (&Interaction, &mut UpgradeButton, &Children),
-        (Changed<Interaction>, With<Button>),
-    >,
-    mut q_mushroom_manager: Query<&mut MushroomManager>,
-    mut q_button_text: Query<(&mut Text, &UpgradeButtonText)>,
-    mut q_spores: Query<&mut Spores>,
-    q_hero: Query<&Hero>,
-) {
-    let mut manager = q_mushroom_manager.single_mut();
-    let mut spores = q_spores.single_mut();
-    let hero = q_hero.single();
+    fn attack(damage_type: DamageType, crit_chance: f32) -> Attack {
+        Attack {
+            amount: 100.0,
+            damage_type,
+            crit_chance,
+            crit_multiplier: 2.0,
+        }
+    }
 
-    for (interaction, mut button, children) in &mut q_interaction {
-        match *interaction {
-            Interaction::Pressed => {
-                if spores.count < button.cost {
-                    continue;
-                }
+    fn defense() -> Defense {
+        Defense {
+            armor: ARMOR_HALF_DAMAGE_POINT,
+            toxic_resistance: 0.25,
+        }
+    }
 
-                spores.count -= button.cost;
-                button.cost *= button.cost_multiplier;
-                let cost = button.cost;
+    #[test]
+    fn physical_damage_is_halved_at_half_damage_armor() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let result = resolve_damage(&attack(DamageType::Physical, 0.0), &defense(), &mut rng);
 
-                let mut update_button_text = |child, text: &String| {
-                    match q_button_text.get_mut(child) {
-                        Ok((mut button_text, upgrade_button_text)) => {
-                            match upgrade_button_text.text_type {
-                                UpgradeTextType::Value => {
-                                    button_text.sections[0].value = text.clone();
-                                }
-                                UpgradeTextType::Cost => {
-                                    button_text.sections[0].value = format!("Cost: {cost}")
-                                }
-                            }
-                        }
-                        Err(_) => todo!(),
-                    };
-                };
+        assert_eq!(
+            result,
+            DamageResult {
+                amount: 50.0,
+                is_crit: false
+            }
+        );
+    }
 
-                let mut update_button_children = |text: String| {
-                    for &child in children.iter() {
-                        update_button_text(child, &text);
-                    }
-                };
+    #[test]
+    fn toxic_damage_is_reduced_by_toxic_resistance() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let result = resolve_damage(&attack(DamageType::Toxic, 0.0), &defense(), &mut rng);
 
-                match button.upgrade_type {
-                    UpgradeType::SporeCount => {
-                        manager.mushroom_template.spore_count += hero.level;
-                        let spore_count = manager.mushroom_template.spore_count;
+        assert_eq!(result.amount, 75.0);
+    }
 
-                        update_button_children(format!("Spore count: {spore_count}"));
-                    }
-                    UpgradeType::MushroomsPerClick => {
-                        manager.spawn_count += 1;
-                        let spawn_count = manager.spawn_count;
+    #[test]
+    fn toxic_resistance_is_capped() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let immune = Defense {
+            armor: 0.0,
+            toxic_resistance: 5.0,
+        };
+        let result = resolve_damage(&attack(DamageType::Toxic, 0.0), &immune, &mut rng);
 
-                        update_button_children(format!("Mushrooms per click: {spawn_count}"));
-                    }
-                    UpgradeType::HP => {
-                        manager.mushroom_template.hp += MUSHROOM_BASE_HP;
-                        let hp = manager.mushroom_template.hp;
+        assert!((result.amount - 100.0 * (1.0 - MAX_RESISTANCE)).abs() < 0.001);
+    }
 
-                        update_button_children(format!("HP: {hp}"));
-                    }
-                    UpgradeType::ATK => {
-                        manager.mushroom_template.atk += MUSHROOM_BASE_ATK;
-                        let atk = manager.mushroom_template.atk;
+    #[test]
+    fn true_damage_ignores_defense() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let result = resolve_damage(&attack(DamageType::True, 0.0), &defense(), &mut rng);
 
-                        update_button_children(format!("ATK: {atk}"));
-                    }
-                    UpgradeType::AtkSpeed => {
-                        manager.mushroom_template.atk_speed += MUSHROOM_BASE_ATK_SPEED;
-                        let atk_speed = manager.mushroom_template.atk_speed;
+        assert_eq!(result.amount, 100.0);
+    }
 
-                        update_button_children(format!("ATK Speed: {atk_speed}"));
-                    }
-                    UpgradeType::MoveSpeed => {
-                        manager.mushroom_template.move_speed += MUSHROOM_BASE_MOVE_SPEED;
-                        let move_speed = manager.mushroom_template.move_speed;
+    #[test]
+    fn guaranteed_crit_multiplies_before_mitigation() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let result = resolve_damage(&attack(DamageType::Physical, 1.0), &defense(), &mut rng);
 
-                        update_button_children(format!("Move Speed: {move_speed}"));
-                    }
-                }
+        assert_eq!(
+            result,
+            DamageResult {
+                amount: 100.0,
+                is_crit: true
             }
-            Interaction::Hovered => {}
-            Interaction::None => {}
-        }
+        );
     }
-}
 
-fn button_system(
-    mut interaction_query: Query<
-        (
-            &Interaction,
-            &mut BackgroundColor,
-            &mut BorderColor,
-            &Children,
-        ),
-        (Changed<Interaction>, With<Button>),
-    >,
-) {
-    for (interaction, mut color, mut border_color, _children) in &mut interaction_query {
-        match *interaction {
-            Interaction::Pressed => {
-                *color = PRESSED_BUTTON.into();
-                border_color.0 = Color::RED;
-            }
-            Interaction::Hovered => {
-                *color = HOVERED_BUTTON.into();
-                border_color.0 = Color::WHITE;
-            }
-            Interaction::None => {
-                *color = NORMAL_BUTTON.into();
-                border_color.0 = Color::BLACK;
-            }
+    #[test]
+    fn crits_follow_the_seeded_rng() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let mut expected_rng = StdRng::seed_from_u64(SEED);
+
+        for _ in 0..100 {
+            let expected_crit = expected_rng.gen::<f32>() < 0.5;
+            let result = resolve_damage(&attack(DamageType::True, 0.5), &defense(), &mut rng);
+
+            assert_eq!(result.is_crit, expected_crit);
+            assert_eq!(result.amount, if expected_crit { 200.0 } else { 100.0 });
         }
     }
-}
 
-fn game_over_system(
-    q_game_manager: Query<&GameManager>,
-    mut q_game_over_ui: Query<&mut Visibility, With<GameOverUI>>,
-    mut q_game_over_text: Query<&mut Text, With<GameOverText>>,
-) {
-    let game_manager = q_game_manager.single();
-    let mut visibility = q_game_over_ui.single_mut();
-    let mut game_over_text = q_game_over_text.single_mut();
+    #[test]
+    fn same_seed_gives_same_outcomes() {
+        let roll = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| resolve_damage(&attack(DamageType::Physical, 0.3), &defense(), &mut rng))
+                .map(|result| result.is_crit)
+                .collect::<Vec<bool>>()
+        };
 
-    if game_manager.game_over {
-        *visibility = Visibility::Visible;
+        assert_eq!(roll(SEED), roll(SEED));
+    }
 
-        if game_manager.victory {
-            game_over_text.sections[0].value = "VICTORY".to_string();
-        } else {
-            game_over_text.sections[0].value = "GAME OVER".to_string();
-        }
+    #[test]
+    fn wave_script_parses() {
+        let script: WaveScript = ron::from_str(WAVE_SCRIPT).unwrap();
+        assert!(!script.waves.is_empty());
+        assert!(script.waves.iter().all(|wave| !wave.groups.is_empty()));
     }
-}
 
-fn summon_button_system(
-    mut commands: Commands,
-    mut q_summon_button_interaction: Query<
-        (&Interaction, &mut BackgroundColor),
-        With<SummonButton>,
-    >,
-    image_manager: Res<ImageManager>,
-    q_mushroom_base: Query<&Transform, With<MushroomBase>>,
-    mut q_summon_manager: Query<&mut SummonManager>,
-    mut q_spores: Query<&mut Spores>,
-    mut q_mushroom_lord_ui_visibility: Query<&mut Visibility, With<MushroomLordUI>>,
-    mut q_mushroom_lord_hp_text: Query<&mut Text, With<MushroomLordHPText>>,
-) {
-    let mushroom_sprite = &image_manager[ImageType::Mushroom];
-    let mushroom_base_position = q_mushroom_base.single().translation;
-    let mut summon_manager = q_summon_manager.single_mut();
-    let mut spores = q_spores.single_mut();
+    #[test]
+    fn every_hero_class_is_defined() {
+        let hero_class_manager: HeroClassManager = ron::from_str(HERO_CLASSES).unwrap();
+        for class in HERO_CLASS_LIST {
+            assert!(hero_class_manager.classes.contains_key(&class));
+        }
+    }
 
-    let mut mushroom_lord_hp_text = q_mushroom_lord_hp_text.single_mut();
-    let mut mushroom_lord_ui_visibility = q_mushroom_lord_ui_visibility.single_mut();
+    #[test]
+    fn growth_curves() {
+        assert_eq!(Growth::Constant.apply(50.0, 10), 50.0);
+        assert_eq!(Growth::Linear(0.5).apply(100.0, 1), 100.0);
+        assert_eq!(Growth::Linear(0.5).apply(100.0, 3), 200.0);
+        assert_eq!(Growth::Exponential(2.0).apply(10.0, 4), 80.0);
+    }
 
-    for (interaction, mut background_color) in &mut q_summon_button_interaction {
-        if spores.count < SUMMON_MINIMUM_SPORE_COUNT {
-            return;
-        } else {
-            *background_color = SUMMON_BUTTON_ACTIVE_COLOR;
+    #[test]
+    fn knight_range_does_not_grow_with_level() {
+        let hero_class_manager: HeroClassManager = ron::from_str(HERO_CLASSES).unwrap();
+        let knight = &hero_class_manager[HeroClass::Knight];
+        assert_eq!(knight.stats_at(1).atk_range, knight.stats_at(50).atk_range);
+        assert!(knight.stats_at(50).hp > knight.stats_at(1).hp);
+    }
+
+    #[test]
+    fn level_ups_keep_the_hero_wounded() {
+        let hero_class_manager: HeroClassManager = ron::from_str(HERO_CLASSES).unwrap();
+        let knight = &hero_class_manager[HeroClass::Knight];
+        let mut hero = knight.hero(HeroClass::Knight, HERO_BASE_LEVEL, &RunModifiers::default());
+        hero.hp = hero.max_hp / 4.0;
+
+        //Several levels in one frame don't add up to a heal either
+        for level in HERO_BASE_LEVEL + 1..=HERO_BASE_LEVEL + 3 {
+            hero.level_up(&knight.stats_at(level));
         }
+        let stats = knight.stats_at(HERO_BASE_LEVEL + 3);
+        assert_eq!(hero.max_hp, stats.hp);
+        assert!((hero.hp - stats.hp / 4.0).abs() < 1e-3);
+        assert_eq!(hero.atk, stats.atk);
+    }
 
-        match *interaction {
-            Interaction::Pressed => {
-                if summon_manager.is_summoned {
-                    return;
-                }
+    #[test]
+    fn exp_overflow_carries_across_levels() {
+        let hero_class_manager: HeroClassManager = ron::from_str(HERO_CLASSES).unwrap();
+        let archer = &hero_class_manager[HeroClass::Archer];
+        let mut hero = archer.hero(HeroClass::Archer, HERO_BASE_LEVEL, &RunModifiers::default());
+
+        //200 for level 2, 300 for level 3, then 450 for level 4
+        let levels = gain_hero_exp(&mut hero, 550.0, archer);
+        assert_eq!(levels, vec![2, 3]);
+        assert_eq!(hero.level, 3);
+        assert_eq!(hero.exp, 50.0);
+        assert_eq!(hero.next_level_exp, 450.0);
+    }
 
-                let mushroom_lord_stats = Mushroom {
-                    hp: MUSHROOM_LORD_BASE_HP
-                        + MUSHROOM_LORD_BASE_HP
-                            * MUSHROOM_LORD_SPORE_MULTIPLIER_HP
-                            * spores.count as f32,
-                    atk: MUSHROOM_LORD_BASE_ATK
-                        + MUSHROOM_LORD_BASE_ATK
-                            * MUSHROOM_LORD_SPORE_MULTIPLIER_ATK
-                            * spores.count as f32,
-                    move_speed: MUSHROOM_LORD_BASE_MOVE_SPEED
-                        + MUSHROOM_LORD_BASE_MOVE_SPEED
-                            * MUSHROOM_LORD_SPORE_MULTIPLIER_MOVE_SPEED
-                            * spores.count as f32,
-                    atk_speed: MUSHROOM_LORD_BASE_ATK_SPEED
-                        + MUSHROOM_LORD_BASE_ATK_SPEED
-                            * MUSHROOM_LORD_SPORE_MULTIPLIER_ATK_SPEED
-                            * spores.count as f32,
-                    atk_range: MUSHROOM_LORD_BASE_ATK_RANGE
-                        + MUSHROOM_LORD_BASE_ATK_RANGE
-                            * MUSHROOM_LORD_SPORE_MULTIPLIER_ATK_RANGE
-                            * spores.count as f32,
-                    spore_count: MUSHROOM_LORD_BASE_SPORE_COUNT,
-                    xp_drop: MUSHROOM_LORD_BASE_EXP_DROP,
-                };
+    #[test]
+    fn table_growth_repeats_last_entry() {
+        let table = Growth::Table(vec![1.0, 2.0, 3.0]);
+        assert_eq!(table.apply(10.0, 1), 10.0);
+        assert_eq!(table.apply(10.0, 3), 30.0);
+        assert_eq!(table.apply(10.0, 10), 30.0);
+    }
 
-                let mushroom_lord_hp = mushroom_lord_stats.hp;
+    fn high_score(mode: GameMode, waves_survived: usize, time: f32) -> HighScore {
+        HighScore {
+            mode,
+            difficulty: Difficulty::Normal,
+            mutators: Vec::new(),
+            waves_survived,
+            peak_spores: 0,
+            time,
+        }
+    }
 
-                commands.spawn((
-                    SpriteBundle {
-                        transform: Transform {
-                            translation: Vec3::new(
-                                mushroom_base_position.x + MUSHROOM_SPAWN_POSITION_OFFSET_AMOUNT,
-                                mushroom_base_position.y + 32.0 * (MUSHROOM_LORD_SCALE - 1.0),
-                                1.0,
-                            ),
-                            scale: (Vec3::splat(MUSHROOM_LORD_SCALE)),
-                            ..default()
-                        },
-                        texture: mushroom_sprite.handle(),
-                        ..default()
-                    },
-                    mushroom_lord_stats,
-                    MushroomLord,
-                    AttackTimer { value: 0.0 },
-                    InCombat { value: false },
-                ));
-                spores.count = 0;
-                summon_manager.is_summoned = true;
+    #[test]
+    fn high_scores_are_ranked_and_capped_per_mode() {
+        let mut table = HighScoreTable::default();
+        for waves in 0..HIGH_SCORES_KEPT + 5 {
+            table.insert(high_score(GameMode::Endless, waves, 10.0));
+        }
+        table.insert(high_score(GameMode::Endless, 3, 99.0));
+        table.insert(high_score(GameMode::Waves, 1, 5.0));
+
+        let endless: Vec<usize> = table
+            .scores
+            .iter()
+            .filter(|score| score.mode == GameMode::Endless)
+            .map(|score| score.waves_survived)
+            .collect();
+        assert_eq!(endless.len(), HIGH_SCORES_KEPT);
+        assert_eq!(endless[0], HIGH_SCORES_KEPT + 4);
+        assert!(endless.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(table.top(GameMode::Waves).count(), 1);
+    }
 
-                *mushroom_lord_ui_visibility = Visibility::Visible;
-                mushroom_lord_hp_text.sections[0].value =
-                    format!("Mushroom Lord HP: {mushroom_lord_hp}");
-            }
-            Interaction::Hovered => {}
-            Interaction::None => {}
+    #[test]
+    fn endless_heroes_get_stronger_with_time_and_kills() {
+        assert_eq!(endless_hero_level(0.0, 0), HERO_BASE_LEVEL);
+        assert!(endless_hero_level(ENDLESS_SECONDS_PER_LEVEL * 3.0, 0) > HERO_BASE_LEVEL);
+        assert!(endless_hero_level(0.0, 20) > endless_hero_level(0.0, 0));
+    }
+
+    #[test]
+    fn campaign_levels_are_winnable() {
+        let campaign: Campaign = ron::from_str(CAMPAIGN).unwrap();
+        assert!(!campaign.levels.is_empty());
+        for level in &campaign.levels {
+            assert!(
+                !level.win_conditions.is_empty(),
+                "{} can't be won",
+                level.name
+            );
+            assert!(!level.waves.is_empty(), "{} has no heroes", level.name);
         }
     }
-}
 
-fn hero_movement_system(
-    mut q_hero: Query<(&mut Hero, &mut Transform, &InCombat)>,
-    time: Res<Time>,
-) {
-    let (hero, mut hero_transform, combat_status) = q_hero.single_mut();
-    if combat_status.value {
-        return;
+    #[test]
+    fn completing_a_level_unlocks_the_next() {
+        let mut progress = CampaignProgress::default();
+        assert!(progress.is_unlocked(0));
+        assert!(!progress.is_unlocked(1));
+
+        progress.complete(0);
+        assert!(progress.is_unlocked(1));
+        assert!(!progress.is_unlocked(2));
+
+        //Replaying an earlier level doesn't lock anything again
+        progress.complete(1);
+        progress.complete(0);
+        assert!(progress.is_unlocked(2));
     }
-    hero_transform.translation.x -= time.delta_seconds() * hero.move_speed;
-}
 
-fn hero_level_system(mut q_hero: Query<&mut Hero>, time: Res<Time>) {
-    let mut hero = q_hero.single_mut();
-    hero.exp += time.delta_seconds() * HERO_EXP_PER_SECOND;
+    #[test]
+    fn mutators_stack_on_top_of_difficulty() {
+        let run_settings = RunSettings {
+            difficulty: Difficulty::Hard,
+            mutators: vec![Mutator::GlassMushrooms, Mutator::UpgradeCap],
+            ..default()
+        };
+        let modifiers = run_settings.modifiers();
 
-    if hero.exp >= hero.next_level_exp {
-        hero.exp = 0.0;
-        hero.level += 1;
+        assert_eq!(modifiers.hero_hp, Difficulty::Hard.modifiers().hero_hp);
+        assert_eq!(modifiers.max_upgrade_level, Some(UPGRADE_CAP_LEVEL));
 
-        hero.hp = hero.level as f32 * HERO_BASE_HP;
-        hero.atk = hero.level as f32 * HERO_BASE_ATK;
-        hero.move_speed = hero.level as f32 * HERO_BASE_MOVE_SPEED;
-        hero.atk_speed = hero.level as f32 * HERO_BASE_ATK_SPEED;
-        hero.atk_range = hero.level as f32 * HERO_BASE_ATK_RANGE;
-        hero.next_level_exp = hero.level as f32 * HERO_BASE_EXP_REQUIRED;
+        let mushroom = modifiers.mushroom(Mushroom::default());
+        assert_eq!(mushroom.hp, MUSHROOM_BASE_HP * 0.5);
+        assert_eq!(mushroom.atk, MUSHROOM_BASE_ATK * 2.0);
+        assert!(mushroom.spore_count < MUSHROOM_BASE_SPORE_COUNT);
     }
-}
 
-//lol
-fn hero_attack_system(
-    mut q_hero: Query<(&mut Hero, &mut Transform, &mut AttackTimer, &mut InCombat)>,
-    mut q_mushroom: Query<(&mut Mushroom, &mut Transform), Without<Hero>>,
-    mut q_hero_sprite: Query<(&mut Sprite, &mut Handle<Image>), With<Hero>>,
-    q_mushroom_base: Query<&Transform, (With<MushroomBase>, Without<Hero>, Without<Mushroom>)>,
-    mut q_game_manager: Query<&mut GameManager>,
-    image_manager: ResMut<ImageManager>,
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-) {
-    let (hero, hero_transform, mut hero_attack_timer, mut hero_combat_status) = q_hero.single_mut();
-    let mushroom_base = q_mushroom_base.single();
-    let mut game_manager = q_game_manager.single_mut();
+    #[test]
+    fn normal_difficulty_changes_nothing() {
+        let modifiers = RunSettings::default().modifiers();
+        let hero_class_manager: HeroClassManager = ron::from_str(HERO_CLASSES).unwrap();
+        let knight = &hero_class_manager[HeroClass::Knight];
 
-    if game_manager.game_over {
-        hero_combat_status.value = false;
-        return;
+        let hero = knight.hero(HeroClass::Knight, HERO_BASE_LEVEL, &modifiers);
+        assert_eq!(hero.hp, knight.stats.hp);
+        assert_eq!(modifiers.upgrade_cost(100), 100);
     }
 
-    let distance_to_base = hero_transform.translation.x - mushroom_base.translation.x;
-    if distance_to_base <= 1.0 {
-        game_manager.game_over = true;
-        game_manager.victory = false;
+    #[test]
+    fn civil_dates_from_unix_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
     }
 
-    let (mut sprite, mut texture) = q_hero_sprite.single_mut();
+    #[test]
+    fn daily_challenge_is_the_same_for_everyone() {
+        let first = RunSettings::daily("2024-05-01");
+        let second = RunSettings::daily("2024-05-01");
+        assert_eq!(first.seed, second.seed);
+        assert_eq!(first.difficulty, second.difficulty);
+        assert_eq!(first.mutators, second.mutators);
+        assert_eq!(
+            first
+                .daily
+                .as_ref()
+                .map(|daily| (daily.class, daily.starting_spores)),
+            second
+                .daily
+                .as_ref()
+                .map(|daily| (daily.class, daily.starting_spores))
+        );
+
+        let mut first_rng = StdRng::seed_from_u64(first.seed);
+        let mut second_rng = StdRng::seed_from_u64(second.seed);
+        let classes = [HeroClass::Knight, HeroClass::Mage];
+        for waves_cleared in 0..5 {
+            let first_wave = endless_wave(waves_cleared, &classes, &mut first_rng);
+            let second_wave = endless_wave(waves_cleared, &classes, &mut second_rng);
+            let counts = |wave: &Wave| {
+                wave.groups
+                    .iter()
+                    .map(|group| (group.class, group.count))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(counts(&first_wave), counts(&second_wave));
+        }
 
-    if hero_attack_timer.value <= 0.0 {
-        hero_combat_status.value = false;
+        assert_ne!(RunSettings::daily("2024-05-02").seed, first.seed);
     }
 
-    q_mushroom.for_each_mut(|mushroom| {
-        let mushroom_transform = mushroom.1;
-        let mut mushroom = mushroom.0;
+    #[test]
+    fn daily_leaderboard_keeps_best_runs_per_day() {
+        let daily_score = |date: &str, waves_survived| DailyScore {
+            date: date.to_string(),
+            seed: fnv1a_hash(date),
+            score: HighScore {
+                mode: GameMode::Daily,
+                difficulty: Difficulty::Normal,
+                mutators: Vec::new(),
+                waves_survived,
+                peak_spores: 0,
+                time: 0.0,
+            },
+        };
 
-        let distance = (hero_transform.translation.x - mushroom_transform.translation.x).abs();
-        if distance <= hero.atk_range {
-            hero_combat_status.value = true;
-            if hero_attack_timer.value > 0.0 {
-                return;
+        let mut leaderboard = DailyLeaderboard::default();
+        for waves_survived in 0..HIGH_SCORES_KEPT + 2 {
+            leaderboard.insert(daily_score("2024-05-01", waves_survived));
+        }
+        leaderboard.insert(daily_score("2024-05-02", 1));
+
+        let today: Vec<usize> = leaderboard
+            .top("2024-05-01")
+            .map(|daily_score| daily_score.score.waves_survived)
+            .collect();
+        assert_eq!(today[0], HIGH_SCORES_KEPT + 1);
+        assert!(today.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(leaderboard.top("2024-05-02").count(), 1);
+        assert_eq!(leaderboard.scores.len(), HIGH_SCORES_KEPT + 1);
+    }
+
+    #[test]
+    fn every_sheet_has_every_clip_inside_its_grid() {
+        let animation_manager: AnimationManager = ron::from_str(ANIMATIONS).unwrap();
+        let hero_class_manager: HeroClassManager = ron::from_str(HERO_CLASSES).unwrap();
+
+        for sheet in [
+            AnimationSheet::Hero,
+            AnimationSheet::Mushroom,
+            AnimationSheet::MushroomLord,
+        ] {
+            let definition = &animation_manager.sheets[&sheet];
+            for clip in [
+                AnimationClip::Idle,
+                AnimationClip::Walk,
+                AnimationClip::Attack,
+                AnimationClip::Hurt,
+                AnimationClip::Die,
+            ] {
+                let clip_definition = &definition.clips[&clip];
+                assert!(clip_definition.frames > 0);
+                assert!(
+                    clip_definition.first + clip_definition.frames
+                        <= definition.columns * definition.rows
+                );
             }
+            assert!(definition.clips[&AnimationClip::Attack]
+                .events
+                .contains(&(1, FrameEvent::Impact)));
+        }
 
-            mushroom.hp -= hero.atk;
+        for class in HERO_CLASS_LIST {
+            assert!(animation_manager
+                .sheets
+                .contains_key(&hero_class_manager[class].sheet));
         }
-    });
+    }
+
+    #[test]
+    fn attack_clip_sends_impact_then_finishes() {
+        let clip = ClipDefinition {
+            first: 8,
+            frames: 3,
+            fps: 10.0,
+            looping: false,
+            events: vec![(1, FrameEvent::Impact)],
+        };
+        let mut animation = Animation::new(AnimationSheet::Hero);
+        animation.restart(AnimationClip::Attack, 1.0);
+
+        assert!(animation.advance(&clip, 0.05).is_empty());
+        assert_eq!(animation.advance(&clip, 0.06), vec![FrameEvent::Impact]);
+        assert_eq!(animation.atlas_index(&clip), 9);
+
+        //A long frame skips ahead but still sends everything on the way
+        animation.restart(AnimationClip::Attack, 1.0);
+        assert_eq!(
+            animation.advance(&clip, 1.0),
+            vec![FrameEvent::Impact, FrameEvent::Finished]
+        );
+        assert!(!animation.is_playing(AnimationClip::Attack));
+        assert_eq!(animation.atlas_index(&clip), 10);
+        assert!(animation.advance(&clip, 1.0).is_empty());
+    }
 
-    let cooldown = 1.0 / hero.atk_speed;
-    if hero_attack_timer.value <= 0.0 {
-        if hero_combat_status.value {
-            *texture = image_manager[ImageType::HeroAttack].handle();
+    #[test]
+    fn looping_clip_wraps_around() {
+        let clip = ClipDefinition {
+            first: 4,
+            frames: 4,
+            fps: 8.0,
+            looping: true,
+            events: Vec::new(),
+        };
+        let mut animation = Animation::new(AnimationSheet::Mushroom);
+        animation.play(AnimationClip::Walk);
 
-            //hack to make it play every time
-            //lazy to make another system for this
-            commands.spawn((
-                AudioBundle {
-                    source: asset_server.load("./ough.ogg"),
-                    settings: PlaybackSettings::DESPAWN,
-                    ..default()
-                },
-                HeroAttackAudio,
-            ));
-        }
-        hero_attack_timer.value = cooldown;
-    } else if hero_attack_timer.value <= (cooldown / 2.0) {
-        *texture = image_manager[ImageType::Hero].handle();
+        animation.advance(&clip, 5.0 / 8.0 + 0.01);
+        assert_eq!(animation.atlas_index(&clip), 5);
+        assert!(animation.is_playing(AnimationClip::Walk));
+
+        //Asking for the clip that's already playing doesn't restart it
+        animation.play(AnimationClip::Walk);
+        assert_eq!(animation.atlas_index(&clip), 5);
     }
 
-    if hero_combat_status.value {
-        sprite.color.set_r(0.0);
-        sprite.color.set_g(1.0);
-        sprite.color.set_b(1.0);
-    } else {
-        sprite.color.set_r(1.0);
-        sprite.color.set_g(1.0);
-        sprite.color.set_b(1.0);
+    fn engaged_unit() -> Unit {
+        let mut unit = Unit::new(0.0);
+        let senses = UnitSenses {
+            target_in_range: true,
+            attack_finished: true,
+            ..default()
+        };
+        unit.attack_cooldown = 1.0;
+        unit.update(&senses, 0.0);
+        unit
     }
-}
 
-fn attack_timer_update_system(mut q_attack_timer: Query<&mut AttackTimer>, time: Res<Time>) {
-    q_attack_timer.for_each_mut(|mut timer| {
-        timer.value -= time.delta_seconds();
-    });
-}
+    #[test]
+    fn unit_waits_out_spawning_then_advances() {
+        let mut unit = Unit::new(0.5);
+        let senses = UnitSenses::default();
 
-fn mushroom_death_system(
-    mut commands: Commands,
-    mut q_mushroom: Query<(Entity, &mut Transform, &mut Mushroom)>,
-    mut q_mushroom_manager: Query<&mut MushroomManager>,
-    mut q_spores: Query<&mut Spores>,
-    mut q_hero: Query<&mut Hero>,
-) {
-    let mut spores = q_spores.single_mut();
-    let mut hero = q_hero.single_mut();
-    let mushroom_manager = q_mushroom_manager.single_mut();
+        assert_eq!(unit.update(&senses, 0.3), None);
+        assert_eq!(unit.update(&senses, 0.3), Some(UnitState::Advancing));
+    }
+
+    #[test]
+    fn unit_stays_engaged_when_the_cooldown_runs_out() {
+        let mut unit = engaged_unit();
+        assert_eq!(unit.state, UnitState::Engaging);
+        let mut senses = UnitSenses {
+            target_in_range: true,
+            attack_finished: true,
+            ..default()
+        };
+
+        assert_eq!(unit.update(&senses, 0.5), None);
+        assert_eq!(unit.update(&senses, 0.5), Some(UnitState::Attacking));
+
+        //The swing plays out even if the target steps away
+        senses.target_in_range = false;
+        senses.attack_finished = false;
+        assert_eq!(unit.update(&senses, 0.1), None);
+
+        //Cooldown still running and the target is still there, so back to waiting, not walking
+        senses.target_in_range = true;
+        senses.attack_finished = true;
+        assert_eq!(unit.update(&senses, 0.1), Some(UnitState::Engaging));
+    }
+
+    #[test]
+    fn stun_interrupts_an_attack_and_dying_is_final() {
+        let mut unit = engaged_unit();
+        unit.attack_cooldown = 0.0;
+        let mut senses = UnitSenses {
+            target_in_range: true,
+            ..default()
+        };
+        assert_eq!(unit.update(&senses, 0.1), Some(UnitState::Attacking));
 
-    q_mushroom.for_each_mut(|mushroom| {
-        if mushroom.2.hp <= 0.0 {
-            commands.entity(mushroom.0).despawn();
-            spores.count += mushroom_manager.mushroom_template.spore_count;
-            hero.exp += mushroom.2.xp_drop;
+        senses.stunned = true;
+        assert_eq!(unit.update(&senses, 0.1), Some(UnitState::Stunned));
+
+        //Busy units don't start new swings once they recover
+        senses.stunned = false;
+        senses.busy = true;
+        assert_eq!(unit.update(&senses, 0.1), Some(UnitState::Engaging));
+
+        senses.dead = true;
+        assert_eq!(unit.update(&senses, 0.1), Some(UnitState::Dying));
+        assert_eq!(unit.update(&UnitSenses::default(), 0.1), None);
+        assert_eq!(unit.state, UnitState::Dying);
+    }
+
+    #[test]
+    fn spore_burst_hands_out_every_spore() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+
+        for total in [0, 1, 3, SPORE_PARTICLES_MAX, SPORE_PARTICLES_MAX * 3 + 5] {
+            let burst = spore_burst(total, &mut rng);
+            assert!(burst.len() as i32 <= SPORE_PARTICLES_MAX);
+            assert_eq!(burst.iter().map(|(amount, _)| amount).sum::<i32>(), total);
+            assert!(burst
+                .iter()
+                .all(|(amount, velocity)| *amount > 0 && velocity.y >= 0.0));
         }
-    })
-}
+    }
 
-fn hero_death_system(mut q_hero: Query<&Hero>, mut q_game_manager: Query<&mut GameManager>) {
-    let hero = q_hero.single_mut();
-    let mut game_manager = q_game_manager.single_mut();
+    #[test]
+    fn damage_text_shows_type_and_crits() {
+        let event = |damage_type, amount, is_crit| DamageEvent {
+            target: Entity::PLACEHOLDER,
+            position: Vec3::ZERO,
+            damage_type,
+            amount,
+            is_crit,
+        };
 
-    if hero.hp <= 0.0 {
-        game_manager.game_over = true;
-        game_manager.victory = true;
+        let toxic = event(DamageType::Toxic, 4.4, false);
+        assert_eq!(toxic.text(), "4");
+        assert_eq!(toxic.color(), TOXIC_DAMAGE_COLOR);
+        assert_eq!(
+            event(DamageType::True, 10.0, false).color(),
+            TRUE_DAMAGE_COLOR
+        );
+
+        //Crits override the type colour
+        let crit = event(DamageType::Physical, 19.6, true);
+        assert_eq!(crit.text(), "20!");
+        assert_eq!(crit.color(), CRIT_DAMAGE_COLOR);
     }
-}
 
-fn mushroom_spawn_system(
-    mut commands: Commands,
-    image_manager: Res<ImageManager>,
-    q_mushroom_base: Query<&Transform, With<MushroomBase>>,
-    mut q_spores: Query<&mut Spores>,
-    q_mushroom_manager: Query<&MushroomManager>,
-    mouse: Res<Input<MouseButton>>,
-) {
-    let mushroom_sprite = &image_manager[ImageType::Mushroom];
-    let mushroom_base_position = q_mushroom_base.single().translation;
+    #[test]
+    fn settings_default_on_and_toggle() {
+        let mut settings: Settings = ron::from_str("(screen_shake: false)").unwrap();
+        assert!(settings.damage_numbers && settings.hit_flash && !settings.screen_shake);
+        assert_eq!(Setting::ScreenShake.label(&settings), "Shake: Off");
 
-    let mut spores = q_spores.single_mut();
-    let mushroom_manager = q_mushroom_manager.single();
+        *Setting::DamageNumbers.value_mut(&mut settings) = false;
+        assert!(!Setting::DamageNumbers.value(&settings));
+        assert_eq!(Setting::DamageNumbers.label(&settings), "Numbers: Off");
+    }
 
-    if spores.count <= 0 {
-        return;
+    #[test]
+    fn screen_shake_trauma_is_capped() {
+        let mut screen_shake = ScreenShake::default();
+        screen_shake.add(SCREEN_SHAKE_SUMMON_TRAUMA);
+        screen_shake.add(SCREEN_SHAKE_SUMMON_TRAUMA);
+        assert_eq!(screen_shake.trauma, 1.0);
     }
 
-    if mouse.just_pressed(MouseButton::Left) {
-        let mut rng = rand::thread_rng();
-        let mut spawn_count = mushroom_manager.spawn_count;
+    #[test]
+    fn health_bar_hides_when_full() {
+        let health_bar = HealthBar {
+            width: UNIT_HEALTH_BAR_WIDTH,
+            always_shown: false,
+        };
+        let mushroom = Mushroom::default();
 
-        if spores.count - spawn_count < 0 {
-            spawn_count = spores.count;
-        }
+        let fraction = HealthBar::fraction(mushroom.hp, mushroom.max_hp);
+        assert_eq!(fraction, 1.0);
+        assert!(!health_bar.is_shown(fraction, false));
+        assert!(health_bar.is_shown(fraction, true));
 
-        for _i in 0..spawn_count {
-            let random_offset: f32 = rng.gen();
+        let fraction = HealthBar::fraction(mushroom.max_hp / 4.0, mushroom.max_hp);
+        assert_eq!(fraction, 0.25);
+        assert!(health_bar.is_shown(fraction, false));
 
-            spores.count -= 1;
-            commands.spawn((
-                SpriteBundle {
-                    transform: Transform {
-                        translation: Vec3::new(
-                            mushroom_base_position.x
-                                + random_offset * MUSHROOM_SPAWN_POSITION_OFFSET_AMOUNT,
-                            mushroom_base_position.y,
-                            0.0,
-                        ),
-                        scale: (Vec3::splat(GLOBAL_SCALE)),
-                        ..default()
-                    },
-                    texture: mushroom_sprite.handle(),
-                    ..default()
-                },
-                mushroom_manager.mushroom_template,
-                AttackTimer { value: 0.0 },
-                InCombat { value: false },
-            ));
-        }
+        //Dead units never show a bar
+        let fraction = HealthBar::fraction(-5.0, mushroom.max_hp);
+        assert!(!health_bar.is_shown(fraction, true));
+        assert_eq!(HealthBar::fraction(150.0, 300.0), 0.5);
     }
-}
 
-fn mushroom_movement_system(
-    mut q_mushroom: Query<(&mut Transform, &Mushroom, &InCombat)>,
-    time: Res<Time>,
-) {
-    q_mushroom.for_each_mut(|mushroom| {
-        let mut transform = mushroom.0;
-        let mushroom_speed = mushroom.1.move_speed;
-        let combat_status = mushroom.2.value;
+    #[test]
+    fn clicks_pick_the_closest_lane() {
+        let battlefield = Battlefield::new(0.0);
+        assert_eq!(battlefield.lanes.len(), LANE_COUNT);
+        assert_eq!(battlefield.nearest_lane(300.0), 0);
+        assert_eq!(battlefield.nearest_lane(-LANE_SPACING * 0.9), 1);
+        assert_eq!(battlefield.nearest_lane(-10_000.0), LANE_COUNT - 1);
+
+        //Mushrooms cross over to a hero's lane, but not inside their own
+        let top = battlefield.lanes[0];
+        let bottom = battlefield.lanes[LANE_COUNT - 1];
+        assert_eq!(
+            lane_change(&battlefield, Vec2::new(0.0, bottom), Vec2::new(200.0, top)),
+            Some(1.0)
+        );
+        assert_eq!(
+            lane_change(&battlefield, Vec2::new(0.0, top), Vec2::new(200.0, bottom)),
+            Some(-1.0)
+        );
+        assert_eq!(
+            lane_change(
+                &battlefield,
+                Vec2::new(0.0, top),
+                Vec2::new(200.0, top + 10.0)
+            ),
+            None
+        );
+    }
 
-        if combat_status {
-            return;
+    #[test]
+    fn scaled_units_stand_on_their_lane() {
+        //The lord is raised by half a tile per extra scale so it doesn't sink into the ground
+        let lord = Transform {
+            translation: Vec3::new(10.0, 32.0 * 2.0, 0.0),
+            scale: Vec3::splat(3.0),
+            ..default()
+        };
+        let mushroom = Transform::from_xyz(40.0, 0.0, 0.0);
+
+        assert_eq!(battlefield_position(&lord), Vec2::new(10.0, 0.0));
+        assert_eq!(
+            battlefield_position(&lord).y,
+            battlefield_position(&mushroom).y
+        );
+
+        //Charges only sweep the hero's own lane
+        let (min, max) = lane_towards_base(Vec2::new(100.0, 0.0), 50.0);
+        assert_eq!(min, Vec2::new(50.0, -LANE_SPACING / 2.0));
+        assert_eq!(max, Vec2::new(100.0, LANE_SPACING / 2.0));
+    }
+
+    #[test]
+    fn hero_targets_are_picked_by_distance_in_two_dimensions() {
+        let mut world = World::new();
+        let close = world.spawn_empty().id();
+        let other_lane = world.spawn_empty().id();
+        let candidate = |entity, position| TargetCandidate {
+            entity,
+            position,
+            hp: 10.0,
+            threat: 1.0,
+        };
+        //Further along x, but in the hero's own lane
+        let candidates = [
+            candidate(close, Vec2::new(-30.0, 0.0)),
+            candidate(other_lane, Vec2::new(-10.0, -LANE_SPACING)),
+        ];
+
+        assert_eq!(
+            select_hero_targets(TargetingPolicy::Nearest, Vec2::ZERO, &candidates),
+            vec![(close, 1.0)]
+        );
+    }
+
+    #[test]
+    fn formations_face_forward_from_the_rear() {
+        let mut rng = StdRng::seed_from_u64(SEED);
+
+        for formation in [Formation::Line, Formation::Wedge, Formation::Blob] {
+            let offsets = formation.offsets(10, &mut rng);
+            assert_eq!(offsets.len(), 10);
+            let rear = offsets.iter().map(|o| o.x).fold(f32::INFINITY, f32::min);
+            assert!(rear.abs() < 1e-4, "{formation:?} rear at {rear}");
         }
-        transform.translation.x += mushroom_speed * time.delta_seconds();
-    });
-}
 
-fn mushroom_attack_system(
-    mut q_hero: Query<(&mut Hero, &mut Transform)>,
-    mut q_mushroom: Query<
-        (
-            &mut Mushroom,
-            &mut Transform,
-            &mut AttackTimer,
-            &mut InCombat,
-            &mut Sprite,
-        ),
-        Without<Hero>,
-    >,
-) {
-    let mut hero = q_hero.single_mut();
+        //The wedge's point is a single mushroom out in front
+        let wedge = Formation::Wedge.offsets(6, &mut rng);
+        let front = wedge.iter().map(|o| o.x).fold(f32::NEG_INFINITY, f32::max);
+        assert_eq!(wedge.iter().filter(|o| o.x == front).count(), 1);
 
-    q_mushroom.for_each_mut(|mushroom_data| {
-        let mut mushroom_transform = mushroom_data.1;
-        let mushroom = mushroom_data.0;
-        let mut attack_timer = mushroom_data.2;
-        let mut combat_status = mushroom_data.3;
+        //A line fills the width of the lane before starting a new rank
+        let line = Formation::Line.offsets(3, &mut rng);
+        assert!(line.iter().all(|o| o.x == 0.0));
+        assert_eq!(Formation::Blob.next(), Formation::Line);
+    }
 
-        let mut sprite = mushroom_data.4;
+    #[test]
+    fn crowd_spreads_out_and_queues_behind_stopped_mushrooms() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..5).map(|_| world.spawn_empty().id()).collect();
+        let agents: HashMap<Entity, CrowdAgent> = entities
+            .iter()
+            .copied()
+            .zip([
+                //Fighting at the front
+                (Vec2::new(100.0, 0.0), true, true),
+                //Right behind it
+                (Vec2::new(100.0 - QUEUE_DISTANCE / 2.0, 2.0), false, true),
+                //Two stacked on the same spot, far from everyone
+                (Vec2::new(0.0, 0.0), false, true),
+                (Vec2::new(0.0, 0.0), false, true),
+                //The lord standing still in front of the stack, not part of the crowd
+                (Vec2::new(QUEUE_DISTANCE / 2.0, 0.0), true, false),
+            ])
+            .map(|(entity, (position, stopped, in_crowd))| {
+                (
+                    entity,
+                    CrowdAgent {
+                        position,
+                        stopped,
+                        in_crowd,
+                    },
+                )
+            })
+            .collect();
+        let mut grid = SpatialGrid::new(SPATIAL_INDEX_CELL_SIZE);
+        for (&entity, agent) in &agents {
+            grid.insert(agent.position, entity);
+        }
 
-        let distance = hero.1.translation.x - mushroom_transform.translation.x;
-        combat_status.value = false;
+        let (push, blocked) = crowd_steering(&agents, &grid, entities[1]);
+        assert!(blocked);
+        assert!(push.x < 0.0);
+        assert!(!crowd_steering(&agents, &grid, entities[0]).1);
 
-        if distance <= mushroom.atk_range {
-            combat_status.value = true;
+        let (push_a, blocked_a) = crowd_steering(&agents, &grid, entities[2]);
+        let (push_b, _) = crowd_steering(&agents, &grid, entities[3]);
+        assert!(!blocked_a);
+        assert!(push_a.length() > 0.0 && push_a != push_b);
+    }
 
-            let cooldown = 1.0 / mushroom.atk_speed;
-            if attack_timer.value <= (cooldown * 0.5) {
-                mushroom_transform.scale.y = 1.0;
-            }
+    #[test]
+    fn crowds_stay_inside_their_lane() {
+        let battlefield = Battlefield::new(0.0);
+        let edge = LANE_SPACING / 2.0 - LANE_EDGE_MARGIN;
+        assert_eq!(clamp_to_lane(&battlefield, 3.0), 3.0);
+        assert_eq!(clamp_to_lane(&battlefield, LANE_SPACING * 0.49), edge);
+        assert_eq!(
+            clamp_to_lane(&battlefield, -LANE_SPACING * 0.51),
+            -LANE_SPACING + edge
+        );
+    }
 
-            if attack_timer.value > 0.0 {
-                return;
-            }
+    #[test]
+    fn pool_reuses_the_latest_release_and_refuses_past_capacity() {
+        let mut pool = EntityPool::new(2);
+        let entities: Vec<Entity> = (0..3).map(Entity::from_raw).collect();
+
+        assert_eq!(pool.acquire(), None);
+        assert!(pool.release(entities[0]));
+        assert!(pool.release(entities[1]));
+        //Full, the caller despawns this one
+        assert!(!pool.release(entities[2]));
+
+        assert_eq!(pool.acquire(), Some(entities[1]));
+        assert!(pool.release(entities[2]));
+        assert_eq!(pool.acquire(), Some(entities[2]));
+        assert_eq!(pool.acquire(), Some(entities[0]));
+        assert_eq!(pool.acquire(), None);
+    }
 
-            hero.0.hp -= mushroom.atk;
+    //Headless world with everything spawn_mushroom and the death systems need
+    fn mushroom_world(pool_capacity: usize) -> World {
+        let mut animation_manager: AnimationManager = ron::from_str(ANIMATIONS).unwrap();
+        for &sheet in animation_manager.sheets.keys() {
+            animation_manager.atlases.insert(sheet, Handle::default());
+        }
+
+        let mut world = World::new();
+        world.insert_resource(animation_manager);
+        world.init_resource::<Time>();
+        world.init_resource::<SpatialIndex>();
+        world.spawn(MushroomManager {
+            pool: EntityPool::new(pool_capacity),
+            ..default()
+        });
+        world
+    }
 
-            if combat_status.value {
-                mushroom_transform.scale.y = 1.1;
+    fn spawn_mushrooms(
+        count: usize,
+    ) -> impl FnMut(Commands, Res<AnimationManager>, Query<&mut MushroomManager>) {
+        move |mut commands, animation_manager, mut q_mushroom_manager| {
+            let mut mushroom_manager = q_mushroom_manager.single_mut();
+            for _ in 0..count {
+                spawn_mushroom(
+                    &mut commands,
+                    &animation_manager,
+                    &mut mushroom_manager.pool,
+                    Vec3::ZERO,
+                    Mushroom::default(),
+                );
             }
-            attack_timer.value = cooldown;
         }
+    }
 
-        if combat_status.value {
-            sprite.color.set_r(0.0);
-            sprite.color.set_g(1.0);
-            sprite.color.set_b(1.0);
-        } else {
-            sprite.color.set_r(1.0);
-            sprite.color.set_g(1.0);
-            sprite.color.set_b(1.0);
-        }
-    });
-}
+    //Long past the die clip and the fade
+    fn kill(world: &mut World, entity: Entity) {
+        world
+            .entity_mut(entity)
+            .insert(DeathSequence { elapsed: 1000.0 });
+    }
 
-fn spore_text_update_system(
-    mut q_spore_text: Query<&mut Text, With<SporeText>>,
-    q_spores: Query<&Spores>,
-) {
-    let mut text = q_spore_text.single_mut();
-    let spore_count = q_spores.single().count;
+    #[test]
+    fn dead_mushrooms_are_stripped_then_pooled_a_frame_later() {
+        let mut world = mushroom_world(MUSHROOM_POOL_CAPACITY);
+        let mut schedule = Schedule::default();
+        schedule.add_systems((death_sequence_system, mushroom_pool_system));
+
+        world.run_system_once(spawn_mushrooms(1));
+        let entity = world
+            .query_filtered::<Entity, With<Mushroom>>()
+            .single(&world);
+        kill(&mut world, entity);
+
+        //Nothing can acquire it in the frame it died
+        schedule.run(&mut world);
+        let parked = world.entity(entity);
+        assert!(parked.contains::<Pooled>());
+        assert!(!parked.contains::<Mushroom>());
+        assert!(!parked.contains::<Animation>());
+        assert!(!parked.contains::<TextureAtlasSprite>());
+        assert!(!parked.contains::<StatusEffects>());
+        assert!(!parked.contains::<HitFlash>());
+        assert_eq!(parked.get::<Visibility>(), Some(&Visibility::Hidden));
+        world.run_system_once(spawn_mushrooms(1));
+        assert_eq!(world.query::<&Mushroom>().iter(&world).count(), 1);
+        assert!(world.entity(entity).contains::<Pooled>());
+
+        //The next frame it is back in the pool and the spawner takes it
+        schedule.run(&mut world);
+        world.run_system_once(spawn_mushrooms(1));
+        let reused = world.entity(entity);
+        assert!(reused.contains::<Mushroom>());
+        assert!(reused.contains::<Animation>());
+        assert!(!reused.contains::<Pooled>());
+        assert_eq!(
+            reused.get::<Children>().map(|children| children.len()),
+            Some(1)
+        );
+
+        //A full pool despawns instead
+        let mut world = mushroom_world(0);
+        let mut schedule = Schedule::default();
+        schedule.add_systems((death_sequence_system, mushroom_pool_system));
+        world.run_system_once(spawn_mushrooms(1));
+        let entity = world
+            .query_filtered::<Entity, With<Mushroom>>()
+            .single(&world);
+        kill(&mut world, entity);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert!(world.get_entity(entity).is_none());
+    }
 
-    text.sections[0].value = format!("Spores: {spore_count}");
-}
+    #[test]
+    fn spawn_orders_take_longer_for_bigger_batches() {
+        let mut order = SpawnOrder::new(1, 4, Formation::Wedge, Mushroom::default());
+        assert_eq!(order.build_time(), 4.0 * MUSHROOM_BUILD_TIME);
+        assert_eq!(order.progress(), 0.0);
+
+        order.elapsed = MUSHROOM_BUILD_TIME;
+        assert!((order.progress() - 0.25).abs() < 1e-6);
+
+        //Overshooting a frame doesn't overfill the bar
+        order.elapsed = order.build_time() * 2.0;
+        assert_eq!(order.progress(), 1.0);
+
+        //Every unit brings its own build time
+        let lord = SpawnOrder::lord(0, mushroom_lord_stats(&MUSHROOM_LORD_TIERS[0], 0));
+        assert_eq!(lord.build_time(), MUSHROOM_LORD_BUILD_TIME);
+        let minion = Mushroom {
+            build_time: SPAWN_MINIONS_BUILD_TIME,
+            ..Mushroom::default()
+        };
+        let minions = SpawnOrder::new(0, SPAWN_MINIONS_COUNT, Formation::Blob, minion);
+        assert_eq!(
+            minions.build_time(),
+            SPAWN_MINIONS_COUNT as f32 * SPAWN_MINIONS_BUILD_TIME
+        );
+    }
 
-fn mushroom_lord_ui_update_system(
-    mut q_mushroom_lord_hp_text: Query<&mut Text, With<MushroomLordHPText>>,
-    q_mushroom_lord: Query<&Mushroom, With<MushroomLord>>,
-) {
-    let q_mushroom_lord_result = q_mushroom_lord.get_single();
-    match q_mushroom_lord_result {
-        Ok(mushroom_lord) => {
-            let mut mushroom_lord_hp_text = q_mushroom_lord_hp_text.single_mut();
-            let hp = mushroom_lord.hp;
+    #[test]
+    fn queued_mushrooms_hold_their_place_under_the_cap() {
+        let mut manager = MushroomManager::default();
+        assert_eq!(manager.room(0), BASE_POPULATION_CAP);
+
+        manager
+            .queue
+            .push_back(SpawnOrder::new(0, 3, Formation::Line, Mushroom::default()));
+        manager
+            .queue
+            .push_back(SpawnOrder::new(2, 2, Formation::Blob, Mushroom::default()));
+        assert_eq!(manager.queued(), 5);
+        assert_eq!(manager.room(10), BASE_POPULATION_CAP - 15);
+
+        //Over the cap just means no room, a queued lord takes none of it
+        assert_eq!(manager.room(BASE_POPULATION_CAP), 0);
+        manager
+            .queue
+            .push_back(SpawnOrder::lord(0, Mushroom::default()));
+        assert_eq!(manager.queued(), 5);
+
+        manager.population_cap += UPGRADE_POPULATION_CAP_AMOUNT;
+        assert_eq!(
+            manager.room(BASE_POPULATION_CAP),
+            UPGRADE_POPULATION_CAP_AMOUNT - 5
+        );
+    }
 
-            mushroom_lord_hp_text.sections[0].value = format!("Mushroom Lord HP: {hp}");
-        }
-        Err(_) => (),
+    #[test]
+    fn right_click_picks_the_closest_hero_under_the_cursor() {
+        let heroes = [
+            (Entity::from_raw(0), Vec2::new(0.0, 0.0)),
+            (Entity::from_raw(1), Vec2::new(30.0, 0.0)),
+            (Entity::from_raw(2), Vec2::new(300.0, 0.0)),
+        ];
+
+        assert_eq!(
+            hero_under_cursor(Vec2::new(20.0, 5.0), heroes.into_iter()),
+            Some(Entity::from_raw(1))
+        );
+        assert_eq!(
+            hero_under_cursor(Vec2::new(-10.0, 0.0), heroes.into_iter()),
+            Some(Entity::from_raw(0))
+        );
+        //Empty ground selects nobody
+        assert_eq!(
+            hero_under_cursor(Vec2::new(150.0, 0.0), heroes.into_iter()),
+            None
+        );
     }
-}
 
-fn hero_hp_text_update_system(
-    mut q_hero_hp_text: Query<&mut Text, With<HeroHPText>>,
-    q_hero: Query<&Hero>,
-) {
-    let mut text = q_hero_hp_text.single_mut();
-    let hero_hp = q_hero.single().hp;
+    #[test]
+    fn endless_waves_stack_up_without_waiting_for_a_clear() {
+        assert!(GameMode::Endless.scheduled_waves());
+        assert!(!GameMode::Campaign.scheduled_waves());
+
+        let mut encounter_manager = EncounterManager::new(
+            GameMode::Endless,
+            WaveScript { waves: Vec::new() },
+            Vec3::ZERO,
+            vec![HeroClass::Knight],
+            1,
+        );
+
+        assert!(encounter_manager.start_wave());
+        let first_wave = encounter_manager.spawn_queue.len();
+        //Nobody died, the second wave joins the heroes still waiting to spawn
+        assert!(encounter_manager.start_wave());
+        assert!(encounter_manager.spawn_queue.len() > first_wave);
+        assert_eq!(encounter_manager.next_wave, 2);
+    }
 
-    text.sections[0].value = format!("Hero HP: {hero_hp}");
-}
+    #[test]
+    fn daily_waves_ignore_combat_rolls() {
+        const FRAMES: usize = 300;
+        const FRAME_TIME: f32 = 0.5;
 
-fn hero_exp_text_update_system(
-    mut q_hero_exp_text: Query<&mut Text, With<HeroEXPText>>,
-    q_hero: Query<&Hero>,
-) {
-    let mut text = q_hero_exp_text.single_mut();
-    let hero_exp = q_hero.single().exp;
+        let settings = RunSettings::daily("2024-05-01");
+        let run = |rolls_per_frame: usize| {
+            let mut animation_manager: AnimationManager = ron::from_str(ANIMATIONS).unwrap();
+            for &sheet in animation_manager.sheets.keys() {
+                animation_manager.atlases.insert(sheet, Handle::default());
+            }
+            let summon_circle = SpriteImage {
+                image_handle: Handle::default(),
+            };
 
-    text.sections[0].value = format!("Hero EXP: {hero_exp}");
-}
+            let mut app = App::new();
+            app.insert_resource(animation_manager)
+                .insert_resource(ImageManager {
+                    images: HashMap::from([(ImageType::SummonCircle, summon_circle)]),
+                })
+                .insert_resource::<HeroClassManager>(ron::from_str(HERO_CLASSES).unwrap())
+                .insert_resource(GameRng(StdRng::seed_from_u64(settings.seed)))
+                .insert_resource(settings.clone())
+                .init_resource::<Time>()
+                .add_systems(Update, encounter_system);
+            app.world.spawn(GameManager::default());
+            app.world.spawn(Battlefield::new(0.0));
+            app.world.spawn(EncounterManager::new(
+                GameMode::Daily,
+                WaveScript { waves: Vec::new() },
+                Vec3::ZERO,
+                HERO_CLASS_LIST.to_vec(),
+                settings.seed,
+            ));
 
-fn hero_level_text_update_system(
-    mut q_hero_level_text: Query<&mut Text, With<HeroLevelText>>,
-    q_hero: Query<&Hero>,
-) {
-    let mut text = q_hero_level_text.single_mut();
-    let hero_level = q_hero.single().level;
+            for _ in 0..FRAMES {
+                //Every hit the player lands rolls GameRng for crits
+                let attack = Mushroom::default().attack(MUSHROOM_BASE_ATK);
+                let defense = Mushroom::default().defense();
+                let mut game_rng = app.world.resource_mut::<GameRng>();
+                for _ in 0..rolls_per_frame {
+                    resolve_damage(&attack, &defense, &mut game_rng.0);
+                }
 
-    text.sections[0].value = format!("Hero Level: {hero_level}");
-}
+                app.world
+                    .resource_mut::<Time>()
+                    .advance_by(Duration::from_secs_f32(FRAME_TIME));
+                app.update();
+            }
 
-fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .insert_resource::<ImageManager>(ImageManager {
-            images: HashMap::new(),
-        })
-        .add_systems(PreStartup, load_assets_system)
-        .add_systems(Startup, (setup_system, setup_ui_system))
-        .add_systems(PreUpdate, (attack_timer_update_system, hero_attack_system))
-        .add_systems(
-            Update,
-            (
-                //MUSHROOM
-                mushroom_spawn_system,
-                mushroom_movement_system,
-                mushroom_death_system,
-                mushroom_attack_system,
-                mushroom_lord_ui_update_system,
-                spore_text_update_system,
-                //HERO
-                hero_hp_text_update_system,
-                hero_exp_text_update_system,
-                hero_level_text_update_system,
-                hero_movement_system,
-                hero_level_system,
-                hero_death_system,
-                //UI
-                button_system,
-                upgrade_button_system,
-                summon_button_system,
-                game_over_system,
-            ),
-        )
-        .run();
+            let waves = app
+                .world
+                .query::<&EncounterManager>()
+                .single(&app.world)
+                .next_wave;
+            let mut heroes: Vec<(Entity, HeroClass, f32)> = app
+                .world
+                .query::<(Entity, &Hero, &Transform)>()
+                .iter(&app.world)
+                .map(|(entity, hero, transform)| (entity, hero.class, transform.translation.y))
+                .collect();
+            heroes.sort_by_key(|(entity, _, _)| *entity);
+            (waves, heroes)
+        };
+
+        //Two players on the same day, one of them lands a lot more hits
+        let (quiet_waves, quiet) = run(0);
+        let (busy_waves, busy) = run(37);
+        assert!(quiet_waves >= 5);
+        assert_eq!(quiet_waves, busy_waves);
+        assert_eq!(quiet, busy);
+    }
 }
@@ -0,0 +1,31 @@
+use bevy::ecs::entity::Entity;
+
+//Free list of parked entities waiting to be reused instead of despawned and spawned again
+//The pool only tracks entities, resetting and hiding them is up to whoever owns the pool
+pub struct EntityPool {
+    free: Vec<Entity>,
+    capacity: usize,
+}
+
+impl EntityPool {
+    pub fn new(capacity: usize) -> Self {
+        EntityPool {
+            free: Vec::new(),
+            capacity,
+        }
+    }
+
+    //Most recently parked first, it is the most likely to still be warm in the cache
+    pub fn acquire(&mut self) -> Option<Entity> {
+        self.free.pop()
+    }
+
+    //False once the pool is full, the caller should despawn the entity instead
+    pub fn release(&mut self, entity: Entity) -> bool {
+        if self.free.len() >= self.capacity {
+            return false;
+        }
+        self.free.push(entity);
+        true
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::{
+    math::{IVec2, Vec2},
+    utils::HashMap,
+};
+
+//Uniform grid for range queries, rebuilt from scratch whenever the positions move
+//Buckets are kept between rebuilds so a crowd that stays put doesn't reallocate
+pub struct SpatialGrid<T> {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Vec2, T)>>,
+    //Bounds of every cell that has been filled since the last clear
+    min_cell: IVec2,
+    max_cell: IVec2,
+    len: usize,
+}
+
+impl<T: Copy> SpatialGrid<T> {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::default(),
+            min_cell: IVec2::MAX,
+            max_cell: IVec2::MIN,
+            len: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn clear(&mut self) {
+        for bucket in self.cells.values_mut() {
+            bucket.clear();
+        }
+        self.min_cell = IVec2::MAX;
+        self.max_cell = IVec2::MIN;
+        self.len = 0;
+    }
+
+    pub fn insert(&mut self, position: Vec2, item: T) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push((position, item));
+        self.min_cell = self.min_cell.min(cell);
+        self.max_cell = self.max_cell.max(cell);
+        self.len += 1;
+    }
+
+    //Everything inside the rectangle, edges included
+    pub fn query_rect(&self, min: Vec2, max: Vec2) -> impl Iterator<Item = (Vec2, T)> + '_ {
+        let min_cell = self.cell(min).max(self.min_cell);
+        let max_cell = self.cell(max).min(self.max_cell);
+
+        (min_cell.x..=max_cell.x)
+            .flat_map(move |x| (min_cell.y..=max_cell.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(move |(position, _)| position.cmpge(min).all() && position.cmple(max).all())
+    }
+
+    //Everything within radius of center, edges included
+    pub fn query_radius(&self, center: Vec2, radius: f32) -> impl Iterator<Item = (Vec2, T)> + '_ {
+        let radius_squared = radius * radius;
+        self.query_rect(center - radius, center + radius)
+            .filter(move |(position, _)| position.distance_squared(center) <= radius_squared)
+    }
+
+    //Closest item no further than max_distance, searching outwards one ring of cells at a time
+    pub fn nearest(&self, center: Vec2, max_distance: f32) -> Option<(Vec2, T)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let center_cell = self.cell(center);
+        //No ring past this one can hold anything
+        let last_ring = (self.min_cell - center_cell)
+            .abs()
+            .max((self.max_cell - center_cell).abs())
+            .max_element();
+        let mut best: Option<(f32, Vec2, T)> = None;
+
+        for ring in 0..=last_ring {
+            //Anything in this ring is at least (ring - 1) cells away
+            let ring_distance = (ring - 1).max(0) as f32 * self.cell_size;
+            if ring_distance > max_distance {
+                break;
+            }
+            if best.is_some_and(|(distance, _, _)| distance <= ring_distance) {
+                break;
+            }
+
+            let occupied =
+                |cell: &IVec2| cell.cmpge(self.min_cell).all() && cell.cmple(self.max_cell).all();
+            for cell in ring_cells(center_cell, ring).filter(occupied) {
+                let Some(bucket) = self.cells.get(&cell) else {
+                    continue;
+                };
+                for &(position, item) in bucket {
+                    let distance = position.distance(center);
+                    if distance <= max_distance
+                        && best.is_none_or(|(best_distance, _, _)| distance < best_distance)
+                    {
+                        best = Some((distance, position, item));
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, position, item)| (position, item))
+    }
+}
+
+//Cells on the border of the square ring cells away from center
+fn ring_cells(center: IVec2, ring: i32) -> impl Iterator<Item = IVec2> {
+    (-ring..=ring).flat_map(move |x| {
+        (-ring..=ring)
+            .filter(move |&y| x.abs() == ring || y.abs() == ring)
+            .map(move |y| center + IVec2::new(x, y))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const SEED: u64 = 55;
+
+    fn scattered_positions(count: usize) -> Vec<Vec2> {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        (0..count)
+            .map(|_| Vec2::new(rng.gen_range(-600.0..600.0), rng.gen_range(-120.0..40.0)))
+            .collect()
+    }
+
+    fn grid_of(positions: &[Vec2]) -> SpatialGrid<usize> {
+        let mut grid = SpatialGrid::new(64.0);
+        for (index, &position) in positions.iter().enumerate() {
+            grid.insert(position, index);
+        }
+        grid
+    }
+
+    #[test]
+    fn queries_match_a_brute_force_scan() {
+        let positions = scattered_positions(500);
+        let grid = grid_of(&positions);
+
+        for (center, radius) in [(Vec2::ZERO, 50.0), (Vec2::new(-580.0, 0.0), 250.0)] {
+            let mut found: Vec<usize> = grid.query_radius(center, radius).map(|(_, i)| i).collect();
+            found.sort();
+            let expected: Vec<usize> = (0..positions.len())
+                .filter(|&i| positions[i].distance(center) <= radius)
+                .collect();
+            assert_eq!(found, expected);
+        }
+
+        let (min, max) = (Vec2::new(-100.0, -24.0), Vec2::new(0.0, 24.0));
+        let mut found: Vec<usize> = grid.query_rect(min, max).map(|(_, i)| i).collect();
+        found.sort();
+        let expected: Vec<usize> = (0..positions.len())
+            .filter(|&i| positions[i].cmpge(min).all() && positions[i].cmple(max).all())
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn nearest_finds_the_closest_within_reach() {
+        let positions = scattered_positions(200);
+        let grid = grid_of(&positions);
+
+        for center in [
+            Vec2::ZERO,
+            Vec2::new(900.0, 300.0),
+            Vec2::new(-300.0, -50.0),
+        ] {
+            let expected = (0..positions.len())
+                .min_by(|&a, &b| {
+                    positions[a]
+                        .distance(center)
+                        .total_cmp(&positions[b].distance(center))
+                })
+                .unwrap();
+            assert_eq!(grid.nearest(center, f32::INFINITY).unwrap().1, expected);
+
+            //Out of reach counts as nothing
+            let distance = positions[expected].distance(center);
+            assert!(grid.nearest(center, distance * 0.99).is_none());
+        }
+
+        let mut grid = grid;
+        grid.clear();
+        assert!(grid.nearest(Vec2::ZERO, f32::INFINITY).is_none());
+        assert_eq!(grid.query_radius(Vec2::ZERO, 1000.0).count(), 0);
+    }
+}